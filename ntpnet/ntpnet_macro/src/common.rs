@@ -1,13 +1,57 @@
 use proc_macro2::TokenStream;
 use quote::quote;
-use syn::{FieldsNamed, Ident, Type};
+use syn::{Expr, FieldsNamed, GenericArgument, Ident, PathArguments, Type};
 
-pub fn struct_field_names_types(ast: &syn::DeriveInput) -> Vec<(Ident, Type)> {
+pub enum FieldKind {
+    Single,
+    Vec,
+    Array(Box<Expr>),
+}
+
+fn weighted_field(ty: &Type) -> syn::Result<(Type, FieldKind)> {
+    let error = || syn::Error::new_spanned(ty, "ntpnet_weighted fields must be Vec<T> or [T; N]");
+    match ty {
+        Type::Array(a) => Ok(((*a.elem).clone(), FieldKind::Array(Box::new(a.len.clone())))),
+        Type::Path(p) => {
+            let last = p.path.segments.last().unwrap();
+            match &last.arguments {
+                PathArguments::AngleBracketed(args) if last.ident == "Vec" => {
+                    match args.args.first() {
+                        Some(GenericArgument::Type(t)) => Ok((t.clone(), FieldKind::Vec)),
+                        _ => Err(error()),
+                    }
+                }
+                _ => Err(error()),
+            }
+        }
+        _ => Err(error()),
+    }
+}
+
+pub fn check_weighted_fields(ast: &syn::DeriveInput) -> syn::Result<()> {
+    if let syn::Data::Struct(s) = &ast.data {
+        for f in &s.fields {
+            if f.attrs.iter().any(|a| a.path.is_ident("ntpnet_weighted")) {
+                weighted_field(&f.ty)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn struct_field_names_types(ast: &syn::DeriveInput) -> Vec<(Ident, Type, FieldKind)> {
     match &ast.data {
         syn::Data::Struct(s) => match &s.fields {
             syn::Fields::Named(FieldsNamed { named, .. }) => named
                 .iter()
-                .map(|f| (f.ident.as_ref().unwrap().clone(), f.ty.clone()))
+                .map(|f| {
+                    let (ty, kind) = if f.attrs.iter().any(|a| a.path.is_ident("ntpnet_weighted")) {
+                        weighted_field(&f.ty).expect("weighted fields are checked first")
+                    } else {
+                        (f.ty.clone(), FieldKind::Single)
+                    };
+                    (f.ident.as_ref().unwrap().clone(), ty, kind)
+                })
                 .collect::<Vec<_>>(),
             _ => todo!(),
         },
@@ -17,9 +61,9 @@ pub fn struct_field_names_types(ast: &syn::DeriveInput) -> Vec<(Ident, Type)> {
 
 pub fn field_descriptions_hash_set(ast: &syn::DeriveInput) -> TokenStream {
     let field_descriptions =
-        struct_field_names_types(&ast)
+        struct_field_names_types(ast)
             .iter()
-            .fold(quote! {}, |acc, (field, ty)| {
+            .fold(quote! {}, |acc, (field, ty, _kind)| {
                 let field_str = field.to_string();
                 quote! {
                     #acc
@@ -32,3 +76,45 @@ pub fn field_descriptions_hash_set(ast: &syn::DeriveInput) -> TokenStream {
         )
     }
 }
+
+pub fn field_type_names_hash_map(ast: &syn::DeriveInput) -> TokenStream {
+    let field_type_names =
+        struct_field_names_types(ast)
+            .iter()
+            .fold(quote! {}, |acc, (_field, ty, _kind)| {
+                quote! {
+                    #acc
+                    (::std::any::TypeId::of::<#ty>(), ::std::any::type_name::<#ty>()),
+                }
+            });
+    quote! {
+        ::std::collections::HashMap::from(
+            [#field_type_names]
+        )
+    }
+}
+
+pub fn field_arity_hash_map(ast: &syn::DeriveInput) -> TokenStream {
+    let field_arity =
+        struct_field_names_types(ast)
+            .iter()
+            .fold(quote! {}, |acc, (field, _ty, kind)| {
+                let field_str = field.to_string();
+                let arity = match kind {
+                    FieldKind::Single => quote! {::ntpnet::transition::EdgeArity::Single},
+                    FieldKind::Vec => quote! {::ntpnet::transition::EdgeArity::Any},
+                    FieldKind::Array(len) => {
+                        quote! {::ntpnet::transition::EdgeArity::Fixed(#len)}
+                    }
+                };
+                quote! {
+                    #acc
+                    (#field_str.to_string(), #arity),
+                }
+            });
+    quote! {
+        ::std::collections::HashMap::from(
+            [#field_arity]
+        )
+    }
+}
@@ -1,22 +1,30 @@
 use proc_macro::TokenStream;
-use syn;
 
 mod transition_input_tokens_macro;
-#[proc_macro_derive(TransitionInputTokensMacro)]
+#[proc_macro_derive(TransitionInputTokensMacro, attributes(ntpnet_weighted))]
 pub fn transition_input_tokens_derive(input: TokenStream) -> TokenStream {
     let ast = syn::parse(input).unwrap();
     crate::transition_input_tokens_macro::impl_transition_input_tokens_macro(&ast)
 }
 
 mod transition_output_tokens_macro;
-#[proc_macro_derive(TransitionOutputTokensMacro)]
+#[proc_macro_derive(TransitionOutputTokensMacro, attributes(ntpnet_weighted))]
 pub fn transition_output_tokens_derive(input: TokenStream) -> TokenStream {
     let ast = syn::parse(input).unwrap();
     crate::transition_output_tokens_macro::impl_transition_output_tokens_macro(&ast)
 }
 
 mod transition_macro;
-#[proc_macro_derive(Transition, attributes(ntpnet_transition))]
+#[proc_macro_derive(
+    Transition,
+    attributes(
+        ntpnet_transition,
+        ntpnet_guard,
+        ntpnet_priority,
+        ntpnet_error,
+        ntpnet_async
+    )
+)]
 pub fn transition_derive(input: TokenStream) -> TokenStream {
     let ast = syn::parse(input).unwrap();
     crate::transition_macro::impl_transition_macro(&ast)
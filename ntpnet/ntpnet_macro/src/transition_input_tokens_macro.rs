@@ -4,21 +4,49 @@ use quote::quote;
 use crate::common;
 
 pub fn impl_transition_input_tokens_macro(ast: &syn::DeriveInput) -> TokenStream {
+    if let Err(e) = common::check_weighted_fields(ast) {
+        return e.to_compile_error().into();
+    }
     let name = &ast.ident;
     let unpack =
-        common::struct_field_names_types(&ast)
+        common::struct_field_names_types(ast)
             .iter()
-            .fold(quote! {}, |acc, (field, ty)| {
+            .fold(quote! {}, |acc, (field, ty, kind)| {
                 let field_str = field.to_string();
+                let token = quote! {
+                    map.remove_entry(
+                        &(#field_str.to_string(), ::std::any::TypeId::of::<#ty>())
+                    ).unwrap().1
+                };
+                let value = match kind {
+                    common::FieldKind::Single => quote! {
+                        *#token.downcast::<#ty>().unwrap()
+                    },
+                    common::FieldKind::Vec => quote! {
+                        #token.downcast::<Vec<::ntpnet::Token>>().unwrap()
+                            .into_iter()
+                            .map(|t| *t.downcast::<#ty>().unwrap())
+                            .collect()
+                    },
+                    common::FieldKind::Array(_) => quote! {
+                        #token.downcast::<Vec<::ntpnet::Token>>().unwrap()
+                            .into_iter()
+                            .map(|t| *t.downcast::<#ty>().unwrap())
+                            .collect::<Vec<#ty>>()
+                            .try_into()
+                            .ok()
+                            .unwrap()
+                    },
+                };
                 quote! {
                     #acc
-                    #field: *map.remove_entry(
-                        &(#field_str.to_string(), ::std::any::TypeId::of::<#ty>())
-                    ).unwrap().1.downcast::<#ty>().unwrap(),
+                    #field: #value,
                 }
             });
 
-    let field_descriptions = common::field_descriptions_hash_set(&ast);
+    let field_descriptions = common::field_descriptions_hash_set(ast);
+    let field_type_names = common::field_type_names_hash_map(ast);
+    let field_arity = common::field_arity_hash_map(ast);
 
     let gen = quote! {
         impl ::ntpnet::TransitionInputTokens for #name {
@@ -32,6 +60,12 @@ pub fn impl_transition_input_tokens_macro(ast: &syn::DeriveInput) -> TokenStream
             fn in_edges() -> ::std::collections::HashSet<(String, ::std::any::TypeId)> {
                 #field_descriptions
             }
+            fn type_names() -> ::std::collections::HashMap<::std::any::TypeId, &'static str> {
+                #field_type_names
+            }
+            fn arity() -> ::std::collections::HashMap<String, ::ntpnet::transition::EdgeArity> {
+                #field_arity
+            }
         }
     };
     gen.into()
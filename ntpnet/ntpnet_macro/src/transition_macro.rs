@@ -4,19 +4,20 @@ use proc_macro2::{Delimiter, Ident, TokenTree};
 use quote::quote;
 use std::collections::HashSet;
 
+#[derive(Debug)]
+struct TransitionCallback {
+    name: Ident,
+    input: (Ident, Vec<Ident>),
+    output: (Ident, Vec<Ident>),
+}
+
 pub fn impl_transition_macro(ast: &syn::DeriveInput) -> TokenStream {
     let name = &ast.ident;
-    let lt_token = if let Some(_) = &ast.generics.lt_token {
+    let lt_token = if ast.generics.lt_token.is_some() {
         quote! {< '_ >}
     } else {
         quote! {}
     };
-    #[derive(Debug)]
-    struct TransitionCallback {
-        name: Ident,
-        input: (Ident, Vec<Ident>),
-        output: (Ident, Vec<Ident>),
-    }
     let token_callbacks = get_attr(ast, "ntpnet_transition")
         .iter()
         .map(|ts| {
@@ -40,12 +41,104 @@ pub fn impl_transition_macro(ast: &syn::DeriveInput) -> TokenStream {
             };
             let output = pop_interface(&mut vt);
             TransitionCallback {
-                name: name,
-                input: input,
-                output: output,
+                name,
+                input,
+                output,
             }
         })
         .collect::<Vec<TransitionCallback>>();
+    let guards = get_attr(ast, "ntpnet_guard")
+        .iter()
+        .map(|ts| {
+            let mut vt = ts.clone().into_iter().collect::<Vec<_>>();
+            let input = pop_ident(&mut vt, ts, "ntpnet_guard")?;
+            pop_punct(&mut vt, ts, "ntpnet_guard", ':')?;
+            let guard = pop_ident(&mut vt, ts, "ntpnet_guard")?;
+            expect_end(&vt, "ntpnet_guard")?;
+            if !token_callbacks.iter().any(|tc| tc.input.1.contains(&input)) {
+                return Err(syn::Error::new_spanned(
+                    &input,
+                    format!("ntpnet_guard: {} is not an input of any case", input),
+                ));
+            }
+            Ok((input, guard))
+        })
+        .collect::<syn::Result<Vec<(Ident, Ident)>>>();
+    let guards = match guards {
+        Ok(guards) => guards,
+        Err(e) => return e.to_compile_error().into(),
+    };
+    let priorities = get_attr(ast, "ntpnet_priority")
+        .iter()
+        .map(|ts| {
+            let mut vt = ts.clone().into_iter().collect::<Vec<_>>();
+            let case = pop_case(&mut vt, ts, "ntpnet_priority", &token_callbacks)?;
+            pop_punct(&mut vt, ts, "ntpnet_priority", ':')?;
+            let priority = vt.iter().map(|t| t.to_string()).collect::<String>();
+            match priority.parse::<i32>() {
+                Ok(priority) => Ok((case, priority)),
+                Err(_) => Err(syn::Error::new_spanned(
+                    vt.into_iter().collect::<proc_macro2::TokenStream>(),
+                    format!("ntpnet_priority: priority of {} must be an integer", case),
+                )),
+            }
+        })
+        .collect::<syn::Result<Vec<(Ident, i32)>>>();
+    let priorities = match priorities {
+        Ok(priorities) => priorities,
+        Err(e) => return e.to_compile_error().into(),
+    };
+    let errors = get_attr(ast, "ntpnet_error")
+        .iter()
+        .map(|ts| {
+            let mut vt = ts.clone().into_iter().collect::<Vec<_>>();
+            let case = pop_case(&mut vt, ts, "ntpnet_error", &token_callbacks)?;
+            pop_punct(&mut vt, ts, "ntpnet_error", ':')?;
+            let edge = pop_ident(&mut vt, ts, "ntpnet_error")?;
+            expect_end(&vt, "ntpnet_error")?;
+            Ok((case, edge))
+        })
+        .collect::<syn::Result<Vec<(Ident, Ident)>>>();
+    let errors = match errors {
+        Ok(errors) => errors,
+        Err(e) => return e.to_compile_error().into(),
+    };
+    let error_types = token_callbacks.iter().fold(quote! {}, |acc, tc| {
+        match errors.iter().find(|(case, _)| case == &tc.name) {
+            Some((_, edge)) => {
+                let name = &tc.name;
+                let input = &tc.input.0;
+                let output = &tc.output.0;
+                let edge_str = edge.to_string();
+                quote! {#acc
+                    let (ty, ty_name) = ::ntpnet::transition::error_type::<Self, #input, #output, _>(Self::#name);
+                    error_edges.insert(#edge_str.to_string(), ty);
+                    type_names.insert(ty, ty_name);
+                    out_arity.insert(
+                        (stringify!(#name).to_string(), #edge_str.to_string()),
+                        ::ntpnet::transition::EdgeArity::Single,
+                    );
+                }
+            }
+            None => acc,
+        }
+    });
+    let asyncs = get_attr(ast, "ntpnet_async")
+        .iter()
+        .map(|ts| {
+            let mut vt = ts.clone().into_iter().collect::<Vec<_>>();
+            let mut cases = vec![pop_case(&mut vt, ts, "ntpnet_async", &token_callbacks)?];
+            while !vt.is_empty() {
+                pop_punct(&mut vt, ts, "ntpnet_async", ',')?;
+                cases.push(pop_case(&mut vt, ts, "ntpnet_async", &token_callbacks)?);
+            }
+            Ok(cases)
+        })
+        .collect::<syn::Result<Vec<Vec<Ident>>>>();
+    let asyncs = match asyncs {
+        Ok(asyncs) => asyncs.into_iter().flatten().collect::<HashSet<Ident>>(),
+        Err(e) => return e.to_compile_error().into(),
+    };
     let interface_enums = token_callbacks
         .iter()
         .fold(vec![], |mut acc, tc| {
@@ -108,6 +201,30 @@ pub fn impl_transition_macro(ast: &syn::DeriveInput) -> TokenStream {
             quote! {<#enum_first as ::ntpnet::TransitionOutputTokens>::out_edges() }
         }
     };
+    let type_names = token_callbacks.iter().fold(quote! {}, |acc, tc| {
+        let case_str = tc.name.to_string();
+        let inputs = tc.input.1.iter().fold(quote! {}, |acc_in, e| {
+            quote! {#acc_in
+                type_names.extend(<#e as ::ntpnet::TransitionInputTokens>::type_names());
+                in_arity.extend(
+                    <#e as ::ntpnet::TransitionInputTokens>::arity()
+                        .into_iter()
+                        .map(|(e, a)| ((#case_str.to_string(), e), a)),
+                );
+            }
+        });
+        let outputs = tc.output.1.iter().fold(quote! {}, |acc_out, e| {
+            quote! {#acc_out
+                type_names.extend(<#e as ::ntpnet::TransitionOutputTokens>::type_names());
+                out_arity.extend(
+                    <#e as ::ntpnet::TransitionOutputTokens>::arity()
+                        .into_iter()
+                        .map(|(e, a)| ((#case_str.to_string(), e), a)),
+                );
+            }
+        });
+        quote! {#acc #inputs #outputs}
+    });
     let cases = token_callbacks.iter().fold(quote! {}, |acc, tc| {
         let inputs = tc.input.1.iter().fold(quote! {}, |acc_cond, e| {
             quote! {#acc_cond <#e as ::ntpnet::TransitionInputTokens>::in_edges(),}
@@ -116,18 +233,43 @@ pub fn impl_transition_macro(ast: &syn::DeriveInput) -> TokenStream {
         let outputs = tc.output.1.iter().fold(quote! {}, |acc_prod, e| {
             quote! {#acc_prod <#e as ::ntpnet::TransitionOutputTokens>::out_edges(),}
         });
-        let outputs = quote! {vec![#outputs]};
+        let (outputs, error) = match errors.iter().find(|(case, _)| case == &tc.name) {
+            Some((_, edge)) => {
+                let edge_str = edge.to_string();
+                (
+                    quote! {vec![#outputs ::std::collections::HashSet::from([
+                        (#edge_str.to_string(), error_edges[#edge_str])
+                    ]),]},
+                    quote! {Some(#edge_str.into())},
+                )
+            }
+            None => (quote! {vec![#outputs]}, quote! {None}),
+        };
+        let guarded = tc.input.1.iter().fold(quote! {}, |acc_guard, e| {
+            let guarded = guards.iter().any(|(input, _)| input == e);
+            quote! {#acc_guard #guarded,}
+        });
+        let guarded = quote! {vec![#guarded]};
+        let priority = priorities
+            .iter()
+            .find(|(case, _)| case == &tc.name)
+            .map_or(0, |(_, p)| *p);
+        let asynchronous = asyncs.contains(&tc.name);
         let name_str = tc.name.to_string();
         quote! {#acc
             (#name_str.into(), ::ntpnet::transition::Case {
                 inputs: #inputs,
                 outputs: #outputs,
+                guarded: #guarded,
+                priority: #priority,
+                error: #error,
+                asynchronous: #asynchronous,
             }),
         }
     });
     let cases = quote! {::std::collections::HashMap::from([#cases])};
-    let callbacks = token_callbacks.iter().fold(quote!{},
-        |acc, tc| {
+    let (callbacks, async_callbacks) = token_callbacks.iter().fold((quote!{}, quote!{}),
+        |(acc, acc_async), tc| {
             let input = &tc.input.0;
             let input_conditions = tc.input.1.iter().enumerate().fold(quote!{},
                 |acc_input, (i, f)| {
@@ -145,40 +287,143 @@ pub fn impl_transition_macro(ast: &syn::DeriveInput) -> TokenStream {
             );
             let name_str = tc.name.to_string();
             let name = &tc.name;
-            quote!{#acc
-                #name_str => {
-                    let output = self.#name( match condition {
-                        #input_conditions
-                        _ => unimplemented!(),
-                    });
+            let output = match errors.iter().find(|(case, _)| case == &tc.name) {
+                Some((_, edge)) => {
+                    let edge_str = edge.to_string();
+                    let error_idx = tc.output.1.len();
+                    quote!{
+                        match output {
+                            Ok(output) => match output {
+                                #output_outcomes
+                            },
+                            Err(e) => {
+                                out_map.insert(
+                                    (#edge_str.into(), ::std::any::Any::type_id(&e)),
+                                    ::ntpnet::Token::new(e),
+                                );
+                                #error_idx
+                            }
+                        }
+                    }
+                }
+                None => quote!{
                     match output {
                         #output_outcomes
                     }
                 },
+            };
+            if asyncs.contains(&tc.name) {
+                (acc, quote!{#acc_async
+                    #name_str => {
+                        let output = self.#name( match condition {
+                            #input_conditions
+                            _ => unimplemented!(),
+                        }).await;
+                        #output
+                    },
+                })
+            } else {
+                (quote!{#acc
+                    #name_str => {
+                        let output = self.#name( match condition {
+                            #input_conditions
+                            _ => unimplemented!(),
+                        });
+                        #output
+                    },
+                }, acc_async)
             }
         }
     );
+    let call_async = if !asyncs.is_empty() {
+        quote! {
+            fn call_async<'a>(&'a mut self, case: &'a str, condition: usize,
+                mut in_map: ::std::collections::HashMap<(String, ::std::any::TypeId), ::ntpnet::Token>,
+            ) -> ::ntpnet::transition::CaseFuture<'a> {
+                ::std::boxed::Box::pin(async move {
+                    let mut out = ::std::collections::HashMap::new();
+                    let r = {
+                        let in_map = &mut in_map;
+                        let out_map = &mut out;
+                        match case {
+                            #async_callbacks
+                            _ => unimplemented!(),
+                        }
+                    };
+                    (out, r)
+                })
+            }
+        }
+    } else {
+        quote! {}
+    };
+    let guard_calls = token_callbacks.iter().fold(quote! {}, |acc, tc| {
+        let name_str = tc.name.to_string();
+        tc.input
+            .1
+            .iter()
+            .enumerate()
+            .fold(acc, |acc_guard, (i, e)| {
+                match guards.iter().find(|(input, _)| input == e) {
+                    Some((_, guard)) => quote! {#acc_guard
+                        (#name_str, #i) => self.#guard(view),
+                    },
+                    None => acc_guard,
+                }
+            })
+    });
+    let guard = if !guards.is_empty() {
+        quote! {
+            fn guard(&self, case: &str, condition: usize, view: &mut ::ntpnet::GuardView) -> bool {
+                match (case, condition) {
+                    #guard_calls
+                    _ => true,
+                }
+            }
+        }
+    } else {
+        quote! {}
+    };
     let gen = quote! {
         #interface_enums
-        impl ::ntpnet::transition::Transition for #name #lt_token {
-            fn description(&self) -> ::ntpnet::transition::Description
+        impl ::ntpnet::transition::Describe for #name #lt_token {
+            fn describe() -> ::ntpnet::transition::Description
             {
+                let mut type_names = ::std::collections::HashMap::new();
+                let mut in_arity = ::std::collections::HashMap::new();
+                let mut out_arity = ::std::collections::HashMap::new();
+                #[allow(unused_mut)]
+                let mut error_edges: ::std::collections::HashMap<String, ::std::any::TypeId> =
+                    ::std::collections::HashMap::new();
+                #type_names
+                #error_types
+                let mut out_edges = #out_edges;
+                out_edges.extend(error_edges.iter().map(|(e, ty)| (e.clone(), *ty)));
                 ::ntpnet::transition::Description {
                     in_edges: #in_edges,
-                    out_edges: #out_edges,
-                    cases: #cases
+                    out_edges: out_edges,
+                    cases: #cases,
+                    type_names: type_names,
+                    in_arity: in_arity,
+                    out_arity: out_arity,
                 }
             }
+        }
+        impl ::ntpnet::transition::Transition for #name #lt_token {
+            fn description(&self) -> ::ntpnet::transition::Description {
+                <Self as ::ntpnet::transition::Describe>::describe()
+            }
             fn call(&mut self, case: &str, condition: usize,
                 in_map: &mut ::std::collections::HashMap<(String, ::std::any::TypeId), ::ntpnet::Token>,
                 out_map: &mut ::std::collections::HashMap<(String, ::std::any::TypeId), ::ntpnet::Token>,
             ) -> usize {
-                let r = match case {
+                match case {
                     #callbacks
                     _ => unimplemented!(),
-                };
-                r
+                }
             }
+            #call_async
+            #guard
         }
     };
     gen.into()
@@ -213,8 +458,66 @@ fn get_attr(ast: &syn::DeriveInput, attr: &str) -> Vec<proc_macro2::TokenStream>
         .fold(quote! {}, |acc, x| quote! {#acc #x,})
         .into_iter()
         .filter_map(|g| match g {
-            Group(g) => Some(g.stream().into()),
+            Group(g) => Some(g.stream()),
             _ => None,
         })
         .collect::<Vec<_>>()
 }
+
+fn pop_ident(
+    vt: &mut Vec<TokenTree>,
+    ts: &proc_macro2::TokenStream,
+    attr: &str,
+) -> syn::Result<Ident> {
+    let message = format!("{}: expected an identifier", attr);
+    if vt.is_empty() {
+        return Err(syn::Error::new_spanned(ts, message));
+    }
+    match vt.remove(0) {
+        TokenTree::Ident(i) => Ok(i),
+        t => Err(syn::Error::new_spanned(t, message)),
+    }
+}
+
+fn pop_case(
+    vt: &mut Vec<TokenTree>,
+    ts: &proc_macro2::TokenStream,
+    attr: &str,
+    token_callbacks: &[TransitionCallback],
+) -> syn::Result<Ident> {
+    let case = pop_ident(vt, ts, attr)?;
+    if token_callbacks.iter().any(|tc| tc.name == case) {
+        Ok(case)
+    } else {
+        Err(syn::Error::new_spanned(
+            &case,
+            format!("{}: {} is not a case of this transition", attr, case),
+        ))
+    }
+}
+
+fn pop_punct(
+    vt: &mut Vec<TokenTree>,
+    ts: &proc_macro2::TokenStream,
+    attr: &str,
+    c: char,
+) -> syn::Result<()> {
+    let message = format!("{}: expected '{}'", attr, c);
+    if vt.is_empty() {
+        return Err(syn::Error::new_spanned(ts, message));
+    }
+    match vt.remove(0) {
+        TokenTree::Punct(p) if p.as_char() == c => Ok(()),
+        t => Err(syn::Error::new_spanned(t, message)),
+    }
+}
+
+fn expect_end(vt: &[TokenTree], attr: &str) -> syn::Result<()> {
+    match vt.first() {
+        Some(t) => Err(syn::Error::new_spanned(
+            t,
+            format!("{}: unexpected token", attr),
+        )),
+        None => Ok(()),
+    }
+}
@@ -4,18 +4,33 @@ use quote::quote;
 use crate::common;
 
 pub fn impl_transition_output_tokens_macro(ast: &syn::DeriveInput) -> TokenStream {
+    if let Err(e) = common::check_weighted_fields(ast) {
+        return e.to_compile_error().into();
+    }
     let name = &ast.ident;
-    let pack = common::struct_field_names_types(&ast).iter().fold(quote!{},
-        |acc, (field, ty)| {
+    let pack = common::struct_field_names_types(ast).iter().fold(quote!{},
+        |acc, (field, ty, kind)| {
             let field_str = field.to_string();
+            let token = match kind {
+                common::FieldKind::Single => quote!{::ntpnet::Token::new(self.#field)},
+                _ => quote!{
+                    ::ntpnet::Token::new(
+                        ::std::iter::IntoIterator::into_iter(self.#field)
+                            .map(::ntpnet::Token::new)
+                            .collect::<Vec<::ntpnet::Token>>()
+                    )
+                },
+            };
             quote!{
                 #acc
-                map.insert((#field_str.to_string(), ::std::any::TypeId::of::<#ty>()), ::ntpnet::Token::new(self.#field));
+                map.insert((#field_str.to_string(), ::std::any::TypeId::of::<#ty>()), #token);
             }
         }
     );
 
-    let field_descriptions = common::field_descriptions_hash_set(&ast);
+    let field_descriptions = common::field_descriptions_hash_set(ast);
+    let field_type_names = common::field_type_names_hash_map(ast);
+    let field_arity = common::field_arity_hash_map(ast);
 
     let gen = quote! {
         impl ::ntpnet::TransitionOutputTokens for #name {
@@ -27,6 +42,12 @@ pub fn impl_transition_output_tokens_macro(ast: &syn::DeriveInput) -> TokenStrea
             fn out_edges() -> ::std::collections::HashSet<(String, ::std::any::TypeId)> {
                 #field_descriptions
             }
+            fn type_names() -> ::std::collections::HashMap<::std::any::TypeId, &'static str> {
+                #field_type_names
+            }
+            fn arity() -> ::std::collections::HashMap<String, ::ntpnet::transition::EdgeArity> {
+                #field_arity
+            }
         }
     };
     gen.into()
@@ -0,0 +1,113 @@
+use crossbeam_channel::{Receiver, Sender};
+
+use crate::state::StateBlockable;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Backpressure {
+    DisableProducer,
+    Block,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Slots {
+    pub tx: Sender<()>,
+    pub rx: Receiver<()>,
+    pub notify: Vec<Sender<StateBlockable>>,
+}
+
+#[derive(Debug)]
+pub(crate) struct Bound {
+    backpressure: Backpressure,
+    slots: Sender<()>,
+    releases: Option<(Receiver<()>, Vec<Sender<StateBlockable>>)>,
+}
+impl Bound {
+    pub fn make(backpressure: Backpressure, slots: Slots, owned: bool) -> Self {
+        Self {
+            backpressure,
+            slots: slots.tx,
+            releases: if owned {
+                Some((slots.rx, slots.notify))
+            } else {
+                None
+            },
+        }
+    }
+    pub fn disable_blocking(&mut self) {
+        self.backpressure = Backpressure::DisableProducer;
+    }
+    pub fn capacity(&self) -> usize {
+        self.slots.capacity().unwrap()
+    }
+    pub fn has_room(&self, n: usize) -> bool {
+        match (self.backpressure, &self.releases) {
+            (Backpressure::Block, None) => true,
+            _ => self.slots.len() + n <= self.slots.capacity().unwrap(),
+        }
+    }
+    pub fn acquire(&self) {
+        match (self.backpressure, &self.releases) {
+            (Backpressure::Block, None) => {
+                let _ = self.slots.send(());
+            }
+            _ => {
+                let _ = self.slots.try_send(());
+            }
+        }
+    }
+    pub fn release(&self) {
+        if let Some((releases, notify)) = &self.releases {
+            if releases.try_recv().is_ok() {
+                for tx in notify {
+                    let _ = tx.send(StateBlockable::Released(()));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crossbeam_channel::bounded;
+    use std::thread;
+    use std::time::Duration;
+
+    fn slots() -> Slots {
+        let (tx, rx) = bounded(1);
+        Slots {
+            tx,
+            rx,
+            notify: vec![],
+        }
+    }
+
+    #[test]
+    fn disabled_backpressure_never_blocks() {
+        let slots = slots();
+        let owner = Bound::make(Backpressure::DisableProducer, slots.clone(), true);
+        let other = Bound::make(Backpressure::DisableProducer, slots, false);
+        other.acquire();
+        assert!(!other.has_room(1));
+        other.acquire();
+        owner.acquire();
+        owner.release();
+        assert!(other.has_room(1));
+    }
+
+    #[test]
+    fn blocking_backpressure_waits_for_a_release() {
+        let slots = slots();
+        let owner = Bound::make(Backpressure::Block, slots.clone(), true);
+        let other = Bound::make(Backpressure::Block, slots, false);
+        other.acquire();
+        assert!(other.has_room(1));
+        assert!(!owner.has_room(1));
+        let waiting = thread::spawn(move || other.acquire());
+        thread::sleep(Duration::from_millis(50));
+        assert!(!waiting.is_finished());
+        owner.release();
+        waiting.join().unwrap();
+        assert!(!owner.has_room(1));
+    }
+}
@@ -0,0 +1,472 @@
+use itertools::Itertools;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::any::TypeId;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use crate::{NamedAny, Token};
+
+type Encoder = Box<dyn Fn(&Token) -> Result<serde_json::Value, String> + Send + Sync>;
+type Decoder = Box<dyn Fn(serde_json::Value) -> Result<Token, String> + Send + Sync>;
+
+#[derive(Debug)]
+pub enum CheckpointError {
+    Io(std::io::Error),
+    Parse(String),
+    UnregisteredType {
+        place: String,
+        ty: String,
+    },
+    UnknownTypeName {
+        place: String,
+        name: String,
+    },
+    Codec {
+        place: String,
+        name: String,
+        message: String,
+    },
+    Timeout(Duration),
+    Finished(usize),
+}
+impl fmt::Display for CheckpointError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CheckpointError::Io(e) => write!(f, "unable to access checkpoint file: {}", e),
+            CheckpointError::Parse(e) => write!(f, "unable to parse checkpoint file: {}", e),
+            CheckpointError::UnregisteredType { place, ty } => write!(
+                f,
+                "place '{}' holds tokens of type '{}' which is not in the registry",
+                place, ty
+            ),
+            CheckpointError::UnknownTypeName { place, name } => write!(
+                f,
+                "place '{}' holds tokens named '{}' which is not in the registry",
+                place, name
+            ),
+            CheckpointError::Codec {
+                place,
+                name,
+                message,
+            } => write!(
+                f,
+                "unable to convert '{}' token in place '{}': {}",
+                name, place, message
+            ),
+            CheckpointError::Timeout(timeout) => {
+                write!(f, "work clusters did not settle within {:?}", timeout)
+            }
+            CheckpointError::Finished(work_cluster) => write!(
+                f,
+                "work cluster {} finished before the checkpoint",
+                work_cluster
+            ),
+        }
+    }
+}
+impl std::error::Error for CheckpointError {}
+
+pub struct TokenRegistry {
+    names: HashMap<TypeId, String>,
+    encoders: HashMap<TypeId, Encoder>,
+    decoders: HashMap<String, Decoder>,
+}
+impl TokenRegistry {
+    pub fn make() -> Self {
+        Self {
+            names: HashMap::new(),
+            encoders: HashMap::new(),
+            decoders: HashMap::new(),
+        }
+    }
+    pub fn register<T>(mut self, name: &str) -> Self
+    where
+        T: Serialize + DeserializeOwned + NamedAny + Send,
+    {
+        assert!(
+            !self.decoders.contains_key(name),
+            "token name '{}' registered twice",
+            name
+        );
+        self.names.insert(TypeId::of::<T>(), name.into());
+        self.encoders.insert(
+            TypeId::of::<T>(),
+            Box::new(|t| {
+                serde_json::to_value(t.downcast_ref::<T>().unwrap()).map_err(|e| e.to_string())
+            }),
+        );
+        self.decoders.insert(
+            name.into(),
+            Box::new(|v| {
+                serde_json::from_value::<T>(v)
+                    .map(Token::new)
+                    .map_err(|e| e.to_string())
+            }),
+        );
+        self
+    }
+    pub(crate) fn encode<'a>(
+        &self,
+        place: &str,
+        ty: &TypeId,
+        tokens: impl Iterator<Item = &'a Token>,
+    ) -> Result<TokenQueue, CheckpointError> {
+        let mut tokens = tokens.peekable();
+        let (name, encoder) = match (self.names.get(ty), self.encoders.get(ty)) {
+            (Some(name), Some(encoder)) => (name, encoder),
+            _ => {
+                return Err(CheckpointError::UnregisteredType {
+                    place: place.into(),
+                    ty: tokens
+                        .peek()
+                        .map_or("unknown", |t| (***t).type_name())
+                        .into(),
+                })
+            }
+        };
+        Ok(TokenQueue {
+            delays: vec![],
+            ty: name.clone(),
+            tokens: tokens
+                .map(|t| {
+                    encoder(t).map_err(|message| CheckpointError::Codec {
+                        place: place.into(),
+                        name: name.clone(),
+                        message,
+                    })
+                })
+                .collect::<Result<_, _>>()?,
+        })
+    }
+    fn decode(&self, place: &str, queue: TokenQueue) -> Result<Vec<Token>, CheckpointError> {
+        let decoder =
+            self.decoders
+                .get(&queue.ty)
+                .ok_or_else(|| CheckpointError::UnknownTypeName {
+                    place: place.into(),
+                    name: queue.ty.clone(),
+                })?;
+        let now = Instant::now();
+        queue
+            .tokens
+            .into_iter()
+            .enumerate()
+            .map(|(i, v)| {
+                let t = decoder(v).map_err(|message| CheckpointError::Codec {
+                    place: place.into(),
+                    name: queue.ty.clone(),
+                    message,
+                })?;
+                Ok(match queue.delays.get(i) {
+                    Some(delay) => t.with_release(now + *delay),
+                    None => t,
+                })
+            })
+            .collect()
+    }
+}
+impl fmt::Debug for TokenRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("TokenRegistry")
+            .field(&self.decoders.keys().sorted().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TokenQueue {
+    #[serde(rename = "type")]
+    pub ty: String,
+    pub tokens: Vec<serde_json::Value>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub delays: Vec<Duration>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub places: BTreeMap<String, Vec<TokenQueue>>,
+}
+impl Checkpoint {
+    pub fn make() -> Self {
+        Self::default()
+    }
+    pub fn from_places(
+        places: &HashMap<String, HashMap<TypeId, VecDeque<Token>>>,
+        registry: &TokenRegistry,
+    ) -> Result<Self, CheckpointError> {
+        let mut checkpoint = Self::make();
+        for (place, ty_v) in places {
+            let queues = checkpoint.places.entry(place.clone()).or_insert(vec![]);
+            for (ty, v) in ty_v.iter().filter(|(_, v)| !v.is_empty()) {
+                queues.push(registry.encode(place, ty, v.iter())?);
+            }
+        }
+        Ok(checkpoint)
+    }
+    pub fn merge(&mut self, other: Checkpoint) {
+        for (place, queues) in other.places {
+            self.places.entry(place).or_insert(vec![]).extend(queues);
+        }
+    }
+    pub fn read(path: &Path) -> Result<Self, CheckpointError> {
+        let s = fs::read_to_string(path).map_err(CheckpointError::Io)?;
+        serde_json::from_str(&s).map_err(|e| CheckpointError::Parse(e.to_string()))
+    }
+    pub fn write(&self, path: &Path) -> Result<(), CheckpointError> {
+        let s = serde_json::to_string_pretty(self)
+            .map_err(|e| CheckpointError::Parse(e.to_string()))?;
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, s).map_err(CheckpointError::Io)?;
+        fs::rename(&tmp, path).map_err(CheckpointError::Io)
+    }
+    pub fn into_tokens(
+        self,
+        registry: &TokenRegistry,
+    ) -> Result<BTreeMap<String, Vec<Token>>, CheckpointError> {
+        let mut places = BTreeMap::new();
+        for (place, queues) in self.places {
+            let mut tokens = vec![];
+            for queue in queues {
+                tokens.extend(registry.decode(&place, queue)?);
+            }
+            places.insert(place, tokens);
+        }
+        Ok(places)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::StateBlockable;
+    use crate::testing::countdown::{Countdown, N};
+    use crate::testing::{reactor_handle, tokens, work_cluster};
+    use crate::Net;
+    use crossbeam_channel::bounded;
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::sync::{Arc, Mutex};
+    use std::task::{Context, Poll, Waker};
+    use std::thread;
+    use tempfile::NamedTempFile;
+
+    #[derive(Default)]
+    struct Gate {
+        state: Mutex<(bool, Option<Waker>)>,
+    }
+    impl Gate {
+        fn open(&self) {
+            let mut state = self.state.lock().unwrap();
+            state.0 = true;
+            if let Some(waker) = state.1.take() {
+                waker.wake();
+            }
+        }
+    }
+    struct Wait(Arc<Gate>);
+    impl Future for Wait {
+        type Output = ();
+        fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+            let mut state = self.0.state.lock().unwrap();
+            if state.0 {
+                Poll::Ready(())
+            } else {
+                state.1 = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+
+    #[derive(crate::TransitionOutputTokensMacro)]
+    struct Checked {
+        checked: u32,
+    }
+    #[derive(crate::Transition)]
+    #[ntpnet_transition(hold: Input(N) -> Output(Checked))]
+    #[ntpnet_async(hold)]
+    struct Held {
+        gate: Arc<Gate>,
+    }
+    impl Held {
+        async fn hold(&mut self, i: Input) -> Output {
+            let Input::N(N { n }) = i;
+            Wait(self.gate.clone()).await;
+            Output::Checked(Checked { checked: n })
+        }
+    }
+
+    fn registry() -> Arc<TokenRegistry> {
+        Arc::new(
+            TokenRegistry::make()
+                .register::<u32>("u32")
+                .register::<String>("string"),
+        )
+    }
+
+    fn values<T: Clone + 'static>(tokens: &[Token]) -> Vec<T> {
+        tokens
+            .iter()
+            .map(|t| t.downcast_ref::<T>().unwrap().clone())
+            .collect()
+    }
+
+    #[test]
+    fn checkpoint_round_trips_through_a_file() {
+        let mut places = HashMap::new();
+        places.insert(
+            "N".to_string(),
+            HashMap::from([(
+                TypeId::of::<u32>(),
+                [3u32, 1, 2].map(Token::new).into_iter().collect(),
+            )]),
+        );
+        places.insert(
+            "S".to_string(),
+            HashMap::from([(
+                TypeId::of::<String>(),
+                VecDeque::from([Token::new("a".to_string())]),
+            )]),
+        );
+        let file = NamedTempFile::new().unwrap();
+        Checkpoint::from_places(&places, &registry())
+            .unwrap()
+            .write(file.path())
+            .unwrap();
+        let restored = Checkpoint::read(file.path())
+            .unwrap()
+            .into_tokens(&registry())
+            .unwrap();
+        assert_eq!(values::<u32>(&restored["N"]), vec![3, 1, 2]);
+        assert_eq!(values::<String>(&restored["S"]), vec!["a".to_string()]);
+    }
+
+    #[test]
+    fn checkpoint_reports_unregistered_types() {
+        let mut places = HashMap::new();
+        places.insert(
+            "F".to_string(),
+            HashMap::from([(TypeId::of::<f64>(), VecDeque::from([Token::new(1.5f64)]))]),
+        );
+        assert!(matches!(
+            Checkpoint::from_places(&places, &registry()),
+            Err(CheckpointError::UnregisteredType { place, ty }) if place == "F" && ty == "f64"
+        ));
+    }
+
+    #[test]
+    fn checkpoint_waits_for_async_calls() {
+        let gate = Arc::new(Gate::default());
+        let held = gate.clone();
+        let net = Net::make()
+            .set_start_tokens("N", vec![Token::new(7u32)])
+            .add_typed_transition("h", move || Held { gate: held.clone() })
+            .place_to_transition("N", "n", "h")
+            .transition_to_place("h", "checked", "C");
+        let (mut wc, control_tx) = work_cluster(net);
+        wc.begin();
+        wc.fire();
+        let (barrier_tx, barrier_rx) = bounded(1);
+        let (checkpoint_tx, checkpoint_rx) = bounded(1);
+        control_tx.send(StateBlockable::Pause(())).unwrap();
+        control_tx
+            .send(StateBlockable::Barrier(barrier_tx))
+            .unwrap();
+        control_tx
+            .send(StateBlockable::Checkpoint((registry(), checkpoint_tx)))
+            .unwrap();
+        wc.fire();
+        assert!(barrier_rx.try_recv().is_err());
+        assert!(checkpoint_rx.try_recv().is_err());
+        gate.open();
+        wc.fire();
+        assert!(barrier_rx.try_recv().is_ok());
+        let restored = checkpoint_rx
+            .try_recv()
+            .unwrap()
+            .unwrap()
+            .into_tokens(&registry())
+            .unwrap();
+        assert_eq!(values::<u32>(&restored["C"]), vec![7]);
+        assert!(restored["N"].is_empty());
+        assert_eq!(tokens::<u32>(&wc.take_places(), "C"), vec![7]);
+    }
+
+    fn countdown(n: Token) -> Net {
+        Net::make()
+            .set_start_tokens("N", vec![n])
+            .add_typed_transition("c", || Countdown {})
+            .place_to_transition("N", "n", "c")
+            .transition_to_place("c", "n", "N")
+            .transition_to_place("c", "done", "D")
+    }
+
+    #[test]
+    fn checkpoint_keeps_release_delays() {
+        let hour = Duration::from_secs(3600);
+        let (mut wc, control_tx) = work_cluster(countdown(
+            Token::new(0u32).with_release(Instant::now() + hour),
+        ));
+        wc.begin();
+        let (checkpoint_tx, checkpoint_rx) = bounded(1);
+        control_tx
+            .send(StateBlockable::Checkpoint((registry(), checkpoint_tx)))
+            .unwrap();
+        wc.fire();
+        let file = NamedTempFile::new().unwrap();
+        checkpoint_rx
+            .try_recv()
+            .unwrap()
+            .unwrap()
+            .write(file.path())
+            .unwrap();
+        let restored = Checkpoint::read(file.path())
+            .unwrap()
+            .into_tokens(&registry())
+            .unwrap();
+        assert_eq!(values::<u32>(&restored["N"]), vec![0]);
+        let release = restored["N"][0].release().unwrap();
+        assert!(release > Instant::now() + hour - Duration::from_secs(60));
+    }
+
+    #[test]
+    fn checkpoint_keeps_paused_clusters_paused() {
+        let handle = reactor_handle(countdown(
+            Token::new(0u32).with_release(Instant::now() + Duration::from_millis(20)),
+        ));
+        handle.pause();
+        handle
+            .checkpoint(&registry(), Duration::from_secs(10))
+            .unwrap();
+        thread::sleep(Duration::from_millis(50));
+        handle.shutdown();
+        let places = handle.join();
+        assert!(tokens::<u32>(&places, "D").is_empty());
+    }
+
+    #[test]
+    fn checkpoint_reports_finished_clusters() {
+        let handle = reactor_handle(countdown(Token::new(0u32)));
+        handle.shutdown();
+        while !handle.is_finished() {
+            thread::yield_now();
+        }
+        assert!(matches!(
+            handle.checkpoint(&registry(), Duration::from_secs(10)),
+            Err(CheckpointError::Finished(0))
+        ));
+    }
+
+    #[test]
+    fn try_set_start_tokens_from_checkpoint_reports_errors() {
+        let file = NamedTempFile::new().unwrap();
+        let path = file.path().to_path_buf();
+        drop(file);
+        assert!(matches!(
+            Net::make().try_set_start_tokens_from_checkpoint(&path, &registry()),
+            Err(CheckpointError::Io(_))
+        ));
+    }
+}
@@ -0,0 +1,122 @@
+use std::cmp::Reverse;
+use std::collections::VecDeque;
+use std::fmt;
+
+use crate::Token;
+
+#[derive(Clone, Copy)]
+pub enum QueueDiscipline {
+    Fifo,
+    Lifo,
+    LatestOnly,
+    Priority(fn(&Token) -> i64),
+}
+impl QueueDiscipline {
+    pub(crate) fn label(&self) -> &'static str {
+        match self {
+            QueueDiscipline::Fifo => "fifo",
+            QueueDiscipline::Lifo => "lifo",
+            QueueDiscipline::LatestOnly => "latest only",
+            QueueDiscipline::Priority(_) => "priority",
+        }
+    }
+    pub(crate) fn enqueue(&self, q: &mut VecDeque<Token>, t: Token) -> usize {
+        match self {
+            QueueDiscipline::Fifo => q.push_back(t),
+            QueueDiscipline::Lifo => q.push_front(t),
+            QueueDiscipline::LatestOnly => {
+                let dropped = q.len();
+                q.clear();
+                q.push_back(t);
+                return dropped;
+            }
+            QueueDiscipline::Priority(key) => {
+                let k = key(&t);
+                let idx = q.iter().position(|x| key(x) < k).unwrap_or(q.len());
+                q.insert(idx, t);
+            }
+        }
+        0
+    }
+    pub(crate) fn arrange(&self, q: &mut VecDeque<Token>) -> usize {
+        match self {
+            QueueDiscipline::Fifo => {}
+            QueueDiscipline::Lifo => q.make_contiguous().reverse(),
+            QueueDiscipline::LatestOnly => {
+                let dropped = q.len().saturating_sub(1);
+                q.drain(..dropped);
+                return dropped;
+            }
+            QueueDiscipline::Priority(key) => q.make_contiguous().sort_by_key(|t| Reverse(key(t))),
+        }
+        0
+    }
+}
+impl fmt::Debug for QueueDiscipline {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "QueueDiscipline({})", self.label())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(t: &Token) -> i64 {
+        *t.downcast_ref::<u32>().unwrap() as i64 / 10
+    }
+
+    fn queue(ns: &[u32]) -> VecDeque<Token> {
+        ns.iter().cloned().map(Token::new).collect()
+    }
+
+    fn values(q: &VecDeque<Token>) -> Vec<u32> {
+        q.iter()
+            .map(|t| *t.downcast_ref::<u32>().unwrap())
+            .collect()
+    }
+
+    fn enqueue_all(d: QueueDiscipline, ns: &[u32]) -> (Vec<u32>, usize) {
+        let mut q = VecDeque::new();
+        let dropped = ns.iter().map(|n| d.enqueue(&mut q, Token::new(*n))).sum();
+        (values(&q), dropped)
+    }
+
+    fn arrange(d: QueueDiscipline, ns: &[u32]) -> (Vec<u32>, usize) {
+        let mut q = queue(ns);
+        let dropped = d.arrange(&mut q);
+        (values(&q), dropped)
+    }
+
+    #[test]
+    fn enqueue_orders_by_discipline() {
+        let ns = [1, 21, 12, 2, 23];
+        assert_eq!(
+            enqueue_all(QueueDiscipline::Fifo, &ns),
+            (vec![1, 21, 12, 2, 23], 0)
+        );
+        assert_eq!(
+            enqueue_all(QueueDiscipline::Lifo, &ns),
+            (vec![23, 2, 12, 21, 1], 0)
+        );
+        assert_eq!(enqueue_all(QueueDiscipline::LatestOnly, &ns), (vec![23], 4));
+        assert_eq!(
+            enqueue_all(QueueDiscipline::Priority(key), &ns),
+            (vec![21, 23, 12, 1, 2], 0)
+        );
+    }
+
+    #[test]
+    fn arrange_matches_enqueue() {
+        let ns = [1, 21, 12, 2, 23];
+        for d in [
+            QueueDiscipline::Fifo,
+            QueueDiscipline::Lifo,
+            QueueDiscipline::LatestOnly,
+            QueueDiscipline::Priority(key),
+        ] {
+            assert_eq!(arrange(d, &ns), enqueue_all(d, &ns), "{:?}", d);
+        }
+        assert_eq!(arrange(QueueDiscipline::LatestOnly, &[]), (vec![], 0));
+    }
+}
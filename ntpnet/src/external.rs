@@ -0,0 +1,114 @@
+use crossbeam_channel::{Receiver, RecvError, RecvTimeoutError, Sender, TryRecvError};
+use std::any::{type_name, TypeId};
+use std::marker::PhantomData;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::{
+    state::{StateBlockable, StateDelta},
+    NamedAny, Token,
+};
+
+pub struct Injector<T> {
+    place: String,
+    tx: Sender<StateBlockable>,
+    state_delta_notification: Sender<StateDelta>,
+    _alive: Arc<()>,
+    _ty: PhantomData<fn(T)>,
+}
+impl<T: NamedAny + Send> Injector<T> {
+    pub(crate) fn make(
+        place: String,
+        tx: Sender<StateBlockable>,
+        state_delta_notification: Sender<StateDelta>,
+        alive: Arc<()>,
+    ) -> Self {
+        Self {
+            place,
+            tx,
+            state_delta_notification,
+            _alive: alive,
+            _ty: PhantomData,
+        }
+    }
+    pub fn send(&self, t: T) -> Result<(), T> {
+        let mut state_delta = StateDelta::make();
+        state_delta.push(&(self.place.clone(), TypeId::of::<T>()), type_name::<T>());
+        let _ = self.state_delta_notification.send(state_delta);
+        match self
+            .tx
+            .send(StateBlockable::Tokens((TypeId::of::<T>(), Token::new(t))))
+        {
+            Ok(()) => Ok(()),
+            Err(e) => match e.into_inner() {
+                StateBlockable::Tokens((_, t)) => Err(*t.downcast::<T>().unwrap()),
+                _ => unreachable!(),
+            },
+        }
+    }
+}
+impl<T> Drop for Injector<T> {
+    fn drop(&mut self) {
+        let _ = self.state_delta_notification.send(StateDelta::make());
+    }
+}
+
+pub struct Subscription<T> {
+    rx: Receiver<StateBlockable>,
+    _ty: PhantomData<fn() -> T>,
+}
+impl<T: 'static> Subscription<T> {
+    pub(crate) fn make(rx: Receiver<StateBlockable>) -> Self {
+        Self {
+            rx,
+            _ty: PhantomData,
+        }
+    }
+    fn unwrap(send_thing: StateBlockable) -> T {
+        match send_thing {
+            StateBlockable::Tokens((_, t)) => *t.downcast::<T>().unwrap(),
+            _ => unreachable!(),
+        }
+    }
+    pub fn recv(&self) -> Result<T, RecvError> {
+        self.rx.recv().map(Self::unwrap)
+    }
+    pub fn try_recv(&self) -> Result<T, TryRecvError> {
+        self.rx.try_recv().map(Self::unwrap)
+    }
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<T, RecvTimeoutError> {
+        self.rx.recv_timeout(timeout).map(Self::unwrap)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{countdown::Countdown, work_cluster};
+    use crate::Net;
+    use crossbeam_channel::unbounded;
+
+    #[test]
+    fn injected_tokens_reach_subscribers() {
+        let (mut wc, _exit_tx) = work_cluster(
+            Net::make()
+                .add_typed_transition("c", || Countdown {})
+                .place_to_transition("N", "n", "c")
+                .transition_to_place("c", "n", "N")
+                .transition_to_place("c", "done", "D"),
+        );
+        let (tx, rx) = unbounded();
+        wc.add_input("N".into(), rx);
+        let (sub_tx, sub_rx) = unbounded();
+        wc.subscribe(("D".into(), TypeId::of::<u32>()), sub_tx);
+        let injector = Injector::<u32>::make("N".into(), tx, unbounded().0, Arc::new(()));
+        let subscription = Subscription::<u32>::make(sub_rx);
+        injector.send(2).unwrap();
+        wc.begin();
+        wc.fire();
+        assert_eq!(subscription.try_recv(), Ok(0));
+        assert_eq!(subscription.try_recv(), Err(TryRecvError::Empty));
+        drop(wc);
+        assert_eq!(injector.send(3), Err(3));
+    }
+}
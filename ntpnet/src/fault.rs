@@ -0,0 +1,134 @@
+use std::any::Any;
+use std::fmt;
+
+use crate::transition::Transition;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TransitionFault {
+    pub transition: String,
+    pub case: String,
+    pub message: String,
+}
+impl TransitionFault {
+    pub(crate) fn make(transition: &str, case: &str, payload: Box<dyn Any + Send>) -> Self {
+        let message = if let Some(s) = payload.downcast_ref::<&str>() {
+            s.to_string()
+        } else if let Some(s) = payload.downcast_ref::<String>() {
+            s.clone()
+        } else {
+            "unknown panic payload".into()
+        };
+        Self {
+            transition: transition.into(),
+            case: case.into(),
+            message,
+        }
+    }
+}
+impl fmt::Display for TransitionFault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "transition '{}' faulted in case '{}': {}",
+            self.transition, self.case, self.message
+        )
+    }
+}
+
+pub enum FaultPolicy {
+    Restart(Box<dyn FnMut() -> Box<dyn Transition> + Send>),
+    DropTokens,
+    RouteTo(String),
+    Shutdown,
+}
+impl FaultPolicy {
+    pub(crate) fn label(&self) -> String {
+        match self {
+            FaultPolicy::Restart(_) => "restart".into(),
+            FaultPolicy::DropTokens => "drop tokens".into(),
+            FaultPolicy::RouteTo(place) => format!("route to {}", place),
+            FaultPolicy::Shutdown => "shutdown".into(),
+        }
+    }
+}
+impl fmt::Debug for FaultPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "FaultPolicy({})", self.label())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::countdown::N;
+    use crate::testing::{run, tokens, work_cluster};
+    use crate::{Net, Token};
+
+    #[derive(crate::TransitionOutputTokensMacro)]
+    struct Checked {
+        checked: u32,
+    }
+    #[derive(crate::Transition)]
+    #[ntpnet_transition(check: Input(N) -> Output(Checked))]
+    struct Fragile {
+        calls: u32,
+    }
+    impl Fragile {
+        fn check(&mut self, i: Input) -> Output {
+            let Input::N(N { n }) = i;
+            self.calls += 1;
+            if n == 0 {
+                panic!("zero");
+            }
+            Output::Checked(Checked {
+                checked: self.calls,
+            })
+        }
+    }
+
+    fn net(policy: FaultPolicy) -> Net {
+        Net::make()
+            .set_start_tokens("N", [1u32, 0, 2].map(Token::new).into())
+            .add_typed_transition("f", || Fragile { calls: 0 })
+            .place_to_transition("N", "n", "f")
+            .transition_to_place("f", "checked", "C")
+            .set_fault_policy("f", policy)
+    }
+
+    #[test]
+    fn drop_tokens_keeps_the_instance() {
+        let places = run(net(FaultPolicy::DropTokens));
+        assert_eq!(tokens::<u32>(&places, "C"), vec![1, 3]);
+        assert!(tokens::<u32>(&places, "N").is_empty());
+    }
+
+    #[test]
+    fn restart_replaces_the_instance() {
+        let restart = FaultPolicy::Restart(Box::new(|| Box::new(Fragile { calls: 0 })));
+        assert_eq!(tokens::<u32>(&run(net(restart)), "C"), vec![1, 1]);
+    }
+
+    #[test]
+    fn route_to_emits_a_fault_token() {
+        let places = run(net(FaultPolicy::RouteTo("F".into())));
+        assert_eq!(tokens::<u32>(&places, "C"), vec![1, 3]);
+        assert_eq!(
+            tokens::<TransitionFault>(&places, "F"),
+            vec![TransitionFault {
+                transition: "f".into(),
+                case: "check".into(),
+                message: "zero".into(),
+            }]
+        );
+    }
+
+    #[test]
+    fn shutdown_stops_the_cluster() {
+        let (mut wc, _exit_tx) = work_cluster(net(FaultPolicy::Shutdown));
+        wc.begin();
+        assert!(wc.fire());
+        let places = wc.take_places();
+        assert_eq!(tokens::<u32>(&places, "C"), vec![1]);
+        assert_eq!(tokens::<u32>(&places, "N"), vec![2]);
+    }
+}
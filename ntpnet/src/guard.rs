@@ -0,0 +1,94 @@
+use bimap::BiMap;
+use std::any::TypeId;
+use std::collections::HashMap;
+
+use crate::state::State;
+
+pub struct GuardView<'a> {
+    state: &'a State,
+    in_edge_to_place: &'a BiMap<String, String>,
+    selected: HashMap<(String, TypeId), usize>,
+}
+impl<'a> GuardView<'a> {
+    pub(crate) fn make(state: &'a State, in_edge_to_place: &'a BiMap<String, String>) -> Self {
+        Self {
+            state,
+            in_edge_to_place,
+            selected: HashMap::new(),
+        }
+    }
+    pub fn iter<T: 'static>(&self, edge: &str) -> impl Iterator<Item = &'a T> {
+        let state = self.state;
+        self.in_edge_to_place
+            .get_by_left(edge)
+            .and_then(|place| state.queue(&(place.clone(), TypeId::of::<T>())))
+            .into_iter()
+            .flatten()
+            .map(|t| t.downcast_ref::<T>().unwrap())
+    }
+    pub fn head<T: 'static>(&self, edge: &str) -> Option<&'a T> {
+        self.iter::<T>(edge).next()
+    }
+    pub fn find<T: 'static, F: Fn(&T) -> bool>(&mut self, edge: &str, f: F) -> bool {
+        if let Some(i) = self.iter::<T>(edge).position(f) {
+            let place = self.in_edge_to_place.get_by_left(edge).unwrap().clone();
+            self.selected.insert((place, TypeId::of::<T>()), i);
+            true
+        } else {
+            false
+        }
+    }
+    pub(crate) fn take_selected(self) -> HashMap<(String, TypeId), usize> {
+        self.selected
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::{run, tokens};
+    use crate::{GuardView, Net, Token};
+
+    #[derive(crate::TransitionInputTokensMacro)]
+    struct Num {
+        n: u32,
+    }
+    #[derive(crate::TransitionOutputTokensMacro)]
+    struct Kept {
+        kept: u32,
+    }
+    #[derive(crate::Transition)]
+    #[ntpnet_transition(keep: Input(Num) -> Output(Kept))]
+    #[ntpnet_guard(Num: even)]
+    struct Even {}
+    impl Even {
+        fn even(&self, view: &mut GuardView) -> bool {
+            view.find("n", |n: &u32| n.is_multiple_of(2))
+        }
+        fn keep(&mut self, i: Input) -> Output {
+            let Input::Num(Num { n }) = i;
+            Output::Kept(Kept { kept: n })
+        }
+    }
+
+    #[test]
+    fn guard_selects_matching_tokens() {
+        let places = run(Net::make()
+            .set_start_tokens("N", [1u32, 4, 3, 6, 5].map(Token::new).into())
+            .add_typed_transition("e", || Even {})
+            .place_to_transition("N", "n", "e")
+            .transition_to_place("e", "kept", "K"));
+        assert_eq!(tokens::<u32>(&places, "K"), vec![4, 6]);
+        assert_eq!(tokens::<u32>(&places, "N"), vec![1, 3, 5]);
+    }
+
+    #[test]
+    fn guard_rejects_when_nothing_matches() {
+        let places = run(Net::make()
+            .set_start_tokens("N", vec![Token::new(1u32)])
+            .add_typed_transition("e", || Even {})
+            .place_to_transition("N", "n", "e")
+            .transition_to_place("e", "kept", "K"));
+        assert!(tokens::<u32>(&places, "K").is_empty());
+        assert_eq!(tokens::<u32>(&places, "N"), vec![1]);
+    }
+}
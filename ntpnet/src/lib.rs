@@ -1,30 +1,62 @@
 #![allow(incomplete_features)]
 
 extern crate ntpnet_macro;
+#[cfg(test)]
+extern crate self as ntpnet;
 pub use ntpnet_macro::{Transition, TransitionInputTokensMacro, TransitionOutputTokensMacro};
 
 use std::any::Any;
 use std::fmt;
 use std::fmt::{Debug, Formatter};
 use std::ops::Deref;
+use std::time::Instant;
 
 pub trait NamedAny: Any {
     fn type_name(&self) -> &'static str;
+    fn as_any(&self) -> &dyn Any;
 }
 impl<T: Any> NamedAny for T {
     fn type_name(&self) -> &'static str {
         std::any::type_name::<T>()
     }
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
 }
 
-pub struct Token(Box<dyn NamedAny + Send>);
+pub struct Token {
+    value: Box<dyn NamedAny + Send>,
+    release: Option<Instant>,
+    flow: Option<u64>,
+}
 impl Token {
     pub fn new<T: NamedAny + Send>(t: T) -> Self {
         assert!(!<dyn Any>::is::<Self>(&t));
-        Self(Box::new(t))
+        Self {
+            value: Box::new(t),
+            release: None,
+            flow: None,
+        }
+    }
+    pub fn with_release(mut self, release: Instant) -> Self {
+        self.release = Some(release);
+        self
+    }
+    pub fn release(&self) -> Option<Instant> {
+        self.release
+    }
+    pub(crate) fn with_flow(mut self, flow: u64) -> Self {
+        self.flow = Some(flow);
+        self
+    }
+    pub(crate) fn take_flow(&mut self) -> Option<u64> {
+        self.flow.take()
+    }
+    pub fn downcast<T: 'static>(self) -> Result<Box<T>, Box<dyn Any>> {
+        <Box<dyn Any>>::downcast::<T>(self.value)
     }
-    pub fn downcast<T: 'static>(self) -> Result<Box<T>, Box<(dyn Any)>> {
-        <Box<dyn Any>>::downcast::<T>(self.0)
+    pub fn downcast_ref<T: 'static>(&self) -> Option<&T> {
+        (*self.value).as_any().downcast_ref::<T>()
     }
 }
 impl Debug for Token {
@@ -35,34 +67,66 @@ impl Debug for Token {
 impl Deref for Token {
     type Target = dyn NamedAny + Send;
     fn deref(&self) -> &Self::Target {
-        &*self.0
+        &*self.value
     }
 }
 
+mod checkpoint;
+pub use checkpoint::{Checkpoint, CheckpointError, TokenQueue, TokenRegistry};
+mod discipline;
+pub use discipline::QueueDiscipline;
+mod external;
+pub use external::{Injector, Subscription};
+mod fault;
+pub use fault::{FaultPolicy, TransitionFault};
+mod capacity;
+pub use capacity::Backpressure;
+mod guard;
+pub use guard::GuardView;
 mod memory_monitor;
+mod metrics;
 mod multi_reactor;
 mod pseudo_state_monitor;
-pub use multi_reactor::MultiReactor;
+pub use multi_reactor::{BuildOptions, MultiReactor, ReactorHandle};
 mod net;
 pub use net::Net;
+mod observer;
+pub use observer::ReactorObserver;
+pub mod net_file;
+mod partition;
+pub use partition::{maximal_work_clusters, merge_work_clusters};
 mod reactor;
 pub use reactor::reactor;
+mod replay;
+pub use replay::{Firing, Recorder, Recording};
+mod scheduling;
+pub use scheduling::{
+    Candidate, FirstMatch, RoundRobin, SchedulingPolicy, SeededRandom, Weighted,
+};
 mod state;
 pub mod transition;
+#[cfg(test)]
+mod testing;
+mod thread_pool;
+mod trace;
 mod transition_input_tokens;
 pub use transition_input_tokens::TransitionInputTokens;
 mod transition_output_tokens;
 pub use transition_output_tokens::TransitionOutputTokens;
+mod validate;
+pub use validate::NetError;
 mod work_cluster;
 
 pub type TransitionMaker = Box<dyn FnOnce() -> Box<dyn transition::Transition> + Send>;
 
 use clap::{Args, Subcommand};
+use std::net::SocketAddr;
+use std::path::PathBuf;
 #[derive(Subcommand)]
 pub enum ReactorOptions {
     PlotOptions(PlotOptions),
 }
-#[derive(Args, Clone, Default)]
+#[derive(Args, Clone, Debug, Default)]
 pub struct PlotOptions {
     #[arg(short, long)]
     local_state: bool,
@@ -74,6 +138,10 @@ pub struct PlotOptions {
     pseudo_state: bool,
     #[arg(short, long)]
     memory_profile: Option<f64>,
+    #[arg(long)]
+    trace: Option<PathBuf>,
+    #[arg(long)]
+    metrics: Option<SocketAddr>,
 }
 
 impl From<&Option<ReactorOptions>> for PlotOptions {
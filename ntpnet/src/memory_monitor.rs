@@ -5,69 +5,43 @@ use procinfo::pid::statm_self;
 use memory_stats::memory_stats;
 use std::sync::{Arc, Condvar, Mutex};
 use std::thread;
-use std::time::{Duration, Instant};
+use std::time::Duration;
 
-use plotmux::plotsink::PlotSink;
+use crate::observer::Observers;
 
 #[cfg(not(target_os = "macos"))]
-fn plot_mem(t0: Instant, plot_sink: &mut PlotSink) {
-    if let Ok(status) = statm_self() {
-        let now = (Instant::now() - t0).as_secs_f64();
-        plot_sink.plot_series_2d("memory usage", "total (B)", now, status.size as f64);
-        plot_sink.plot_series_2d(
-            "memory usage",
-            "non-swapped (B)",
-            now,
-            status.resident as f64,
-        );
-        plot_sink.plot_series_2d(
-            "memory usage",
-            "shared (B)",
-            now,
-            status.share as f64,
-        );
-        plot_sink.plot_series_2d(
-            "memory usage",
-            "executable (B)",
-            now,
-            status.text as f64,
-        );
-        plot_sink.plot_series_2d(
-            "memory usage",
-            "stack + heap (B)",
-            now,
-            status.data as f64,
-        );
+fn sample_mem() -> Vec<(&'static str, f64)> {
+    let page_size = rustix::param::page_size() as f64;
+    match statm_self() {
+        Ok(status) => vec![
+            ("total (B)", status.size as f64 * page_size),
+            ("non-swapped (B)", status.resident as f64 * page_size),
+            ("shared (B)", status.share as f64 * page_size),
+            ("executable (B)", status.text as f64 * page_size),
+            ("stack + heap (B)", status.data as f64 * page_size),
+        ],
+        Err(_) => vec![],
     }
 }
 
 #[cfg(target_os = "macos")]
-fn plot_mem(t0: Instant, plot_sink: &mut PlotSink) {
-    if let Some(usage) = memory_stats() {
-        let now = (Instant::now() - t0).as_secs_f64();
-        plot_sink.plot_series_2d(
-            "memory usage",
-            "physical memory (B)",
-            now,
-            usage.physical_mem as f64
-        );
-        plot_sink.plot_series_2d(
-            "memory usag",
-            "virtual memory (B)",
-            now,
-            usage.virtual_mem as f64
-        );
+fn sample_mem() -> Vec<(&'static str, f64)> {
+    match memory_stats() {
+        Some(usage) => vec![
+            ("physical memory (B)", usage.physical_mem as f64),
+            ("virtual memory (B)", usage.virtual_mem as f64),
+        ],
+        None => vec![],
     }
 }
 
-pub fn memory_monitor(period: f64, mut plot_sink: PlotSink) -> impl Drop {
+pub fn memory_monitor(period: f64, observers: Observers) -> impl Drop {
     let pair = Arc::new((Mutex::new(false), Condvar::new()));
     let pair2 = pair.clone();
     let t = thread::Builder::new()
         .name("memory_monitor".into())
         .spawn(move || {
-            let t0 = Instant::now();
-            let &(ref lock, ref cvar) = &*pair;
+            let (lock, cvar) = &*pair;
             let mut exit = lock.lock().unwrap();
             loop {
                 let result = cvar
@@ -77,14 +51,16 @@ pub fn memory_monitor(period: f64, mut plot_sink: PlotSink) -> impl Drop {
                 if *exit {
                     break;
                 }
-                plot_mem(t0, &mut plot_sink);
+                let usage = sample_mem();
+                if !usage.is_empty() {
+                    observers.notify(|o| o.on_memory(&usage));
+                }
             }
         })
         .expect("unable to spawn memory monitor thread");
     defer(move || {
-        let &(ref lock, ref cvar) = &*pair2;
-        let mut exit = lock.lock().unwrap();
-        *exit = true;
+        let (lock, cvar) = &*pair2;
+        *lock.lock().unwrap() = true;
         cvar.notify_one();
         t.join().expect("unable to join memory monitor thread")
     })
@@ -0,0 +1,292 @@
+use defer::defer;
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use crate::observer::ReactorObserver;
+
+const BUCKETS: [f64; 12] = [
+    0.0001, 0.0005, 0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 5.0,
+];
+
+#[derive(Debug, Default)]
+struct Histogram {
+    buckets: [u64; BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+impl Histogram {
+    fn observe(&mut self, v: f64) {
+        for (bucket, le) in self.buckets.iter_mut().zip(BUCKETS.iter()) {
+            if v <= *le {
+                *bucket += 1;
+            }
+        }
+        self.sum += v;
+        self.count += 1;
+    }
+}
+
+#[derive(Debug, Default)]
+struct MetricsState {
+    firings: BTreeMap<(usize, String), Histogram>,
+    blocked: BTreeMap<usize, f64>,
+    marking: BTreeMap<(String, String), i64>,
+    memory: BTreeMap<String, f64>,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct MetricsObserver {
+    state: Mutex<MetricsState>,
+}
+impl MetricsObserver {
+    pub fn make() -> Self {
+        Self::default()
+    }
+    pub fn render(&self) -> String {
+        let state = self.state.lock().unwrap();
+        let mut out = String::new();
+        writeln!(
+            out,
+            "# HELP ntpnet_transition_firings_total Completed transition firings."
+        )
+        .unwrap();
+        writeln!(out, "# TYPE ntpnet_transition_firings_total counter").unwrap();
+        for ((wc, t), h) in &state.firings {
+            writeln!(
+                out,
+                "ntpnet_transition_firings_total{{work_cluster=\"{}\",transition=\"{}\"}} {}",
+                wc,
+                escape(t),
+                h.count
+            )
+            .unwrap();
+        }
+        writeln!(
+            out,
+            "# HELP ntpnet_transition_duration_seconds Transition firing duration."
+        )
+        .unwrap();
+        writeln!(out, "# TYPE ntpnet_transition_duration_seconds histogram").unwrap();
+        for ((wc, t), h) in &state.firings {
+            let labels = format!("work_cluster=\"{}\",transition=\"{}\"", wc, escape(t));
+            for (le, bucket) in BUCKETS.iter().zip(h.buckets.iter()) {
+                writeln!(
+                    out,
+                    "ntpnet_transition_duration_seconds_bucket{{{},le=\"{}\"}} {}",
+                    labels, le, bucket
+                )
+                .unwrap();
+            }
+            writeln!(
+                out,
+                "ntpnet_transition_duration_seconds_bucket{{{},le=\"+Inf\"}} {}",
+                labels, h.count
+            )
+            .unwrap();
+            writeln!(
+                out,
+                "ntpnet_transition_duration_seconds_sum{{{}}} {}",
+                labels, h.sum
+            )
+            .unwrap();
+            writeln!(
+                out,
+                "ntpnet_transition_duration_seconds_count{{{}}} {}",
+                labels, h.count
+            )
+            .unwrap();
+        }
+        writeln!(
+            out,
+            "# HELP ntpnet_place_tokens Tokens in each place, as seen by the pseudo-state monitor."
+        )
+        .unwrap();
+        writeln!(out, "# TYPE ntpnet_place_tokens gauge").unwrap();
+        for ((place, ty), n) in &state.marking {
+            writeln!(
+                out,
+                "ntpnet_place_tokens{{place=\"{}\",type=\"{}\"}} {}",
+                escape(place),
+                escape(ty),
+                n
+            )
+            .unwrap();
+        }
+        writeln!(out, "# HELP ntpnet_work_cluster_blocked_seconds_total Time each work cluster spent blocked.").unwrap();
+        writeln!(
+            out,
+            "# TYPE ntpnet_work_cluster_blocked_seconds_total counter"
+        )
+        .unwrap();
+        for (wc, s) in &state.blocked {
+            writeln!(
+                out,
+                "ntpnet_work_cluster_blocked_seconds_total{{work_cluster=\"{}\"}} {}",
+                wc, s
+            )
+            .unwrap();
+        }
+        writeln!(
+            out,
+            "# HELP ntpnet_memory_bytes Process memory usage in bytes."
+        )
+        .unwrap();
+        writeln!(out, "# TYPE ntpnet_memory_bytes gauge").unwrap();
+        for (kind, bytes) in &state.memory {
+            writeln!(
+                out,
+                "ntpnet_memory_bytes{{kind=\"{}\"}} {}",
+                escape(kind),
+                bytes
+            )
+            .unwrap();
+        }
+        out
+    }
+}
+impl ReactorObserver for MetricsObserver {
+    fn on_start(&self, work_cluster: usize, transitions: &[&str]) {
+        let mut state = self.state.lock().unwrap();
+        for t in transitions {
+            state
+                .firings
+                .entry((work_cluster, t.to_string()))
+                .or_default();
+        }
+        state.blocked.entry(work_cluster).or_default();
+    }
+    fn on_fire_end(&self, work_cluster: usize, transition: &str, _case: &str, elapsed: Duration) {
+        self.state
+            .lock()
+            .unwrap()
+            .firings
+            .entry((work_cluster, transition.into()))
+            .or_default()
+            .observe(elapsed.as_secs_f64());
+    }
+    fn on_unblock(&self, work_cluster: usize, blocked: Duration) {
+        *self
+            .state
+            .lock()
+            .unwrap()
+            .blocked
+            .entry(work_cluster)
+            .or_default() += blocked.as_secs_f64();
+    }
+    fn on_marking(&self, place: &str, ty: &str, count: i64) {
+        self.state
+            .lock()
+            .unwrap()
+            .marking
+            .insert((place.into(), ty.into()), count);
+    }
+    fn on_memory(&self, usage: &[(&str, f64)]) {
+        let mut state = self.state.lock().unwrap();
+        for (kind, value) in usage {
+            state
+                .memory
+                .insert(kind.trim_end_matches(" (B)").into(), *value);
+        }
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn serve(stream: TcpStream, metrics: &MetricsObserver) -> io::Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(Duration::from_secs(1)))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request = String::new();
+    reader.read_line(&mut request)?;
+    let mut line = String::new();
+    while reader.read_line(&mut line)? > 2 {
+        line.clear();
+    }
+    let (status, body) = match request.split_whitespace().nth(1) {
+        Some("/metrics") | Some("/") => ("200 OK", metrics.render()),
+        _ => ("404 Not Found", String::new()),
+    };
+    let mut stream = stream;
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
+
+pub fn metrics_server(addr: SocketAddr, metrics: Arc<MetricsObserver>) -> io::Result<impl Drop> {
+    let listener = TcpListener::bind(addr)?;
+    listener.set_nonblocking(true)?;
+    let exit = Arc::new(AtomicBool::new(false));
+    let exit2 = exit.clone();
+    let t = thread::Builder::new()
+        .name("metrics_server".into())
+        .spawn(move || {
+            while !exit.load(Ordering::SeqCst) {
+                match listener.accept() {
+                    Ok((stream, _)) => {
+                        let _ = serve(stream, &metrics);
+                    }
+                    Err(_) => thread::sleep(Duration::from_millis(50)),
+                }
+            }
+        })?;
+    Ok(defer(move || {
+        exit2.store(true, Ordering::SeqCst);
+        t.join().expect("unable to join metrics server thread")
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_reports_observed_events() {
+        let metrics = MetricsObserver::make();
+        metrics.on_start(0, &["a", "b"]);
+        metrics.on_fire_end(0, "a", "run", Duration::from_millis(20));
+        metrics.on_fire_end(0, "a", "run", Duration::from_secs(2));
+        metrics.on_unblock(0, Duration::from_millis(500));
+        metrics.on_unblock(0, Duration::from_millis(250));
+        metrics.on_marking("P\"1", "u32", 3);
+        metrics.on_memory(&[("total (B)", 4096.0)]);
+        let out = metrics.render();
+        let lines = out.lines().collect::<Vec<_>>();
+        for line in [
+            "ntpnet_transition_firings_total{work_cluster=\"0\",transition=\"a\"} 2",
+            "ntpnet_transition_firings_total{work_cluster=\"0\",transition=\"b\"} 0",
+            "ntpnet_transition_duration_seconds_bucket{work_cluster=\"0\",transition=\"a\",le=\"0.01\"} 0",
+            "ntpnet_transition_duration_seconds_bucket{work_cluster=\"0\",transition=\"a\",le=\"0.025\"} 1",
+            "ntpnet_transition_duration_seconds_bucket{work_cluster=\"0\",transition=\"a\",le=\"5\"} 2",
+            "ntpnet_transition_duration_seconds_bucket{work_cluster=\"0\",transition=\"a\",le=\"+Inf\"} 2",
+            "ntpnet_transition_duration_seconds_count{work_cluster=\"0\",transition=\"a\"} 2",
+            "ntpnet_work_cluster_blocked_seconds_total{work_cluster=\"0\"} 0.75",
+            "ntpnet_place_tokens{place=\"P\\\"1\",type=\"u32\"} 3",
+            "ntpnet_memory_bytes{kind=\"total\"} 4096",
+        ] {
+            assert!(lines.contains(&line), "missing {}\n{}", line, out);
+        }
+        assert_eq!(lines.iter().filter(|l| l.starts_with("# TYPE")).count(), 5);
+    }
+
+    #[test]
+    fn metrics_server_reports_bind_errors() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        assert!(metrics_server(addr, Arc::new(MetricsObserver::make())).is_err());
+    }
+}
@@ -1,27 +1,68 @@
-use crossbeam_channel::{bounded, unbounded, Receiver, Sender};
+use crossbeam_channel::{bounded, unbounded, Receiver, RecvTimeoutError, Sender};
+use itertools::Itertools;
 use std::any::TypeId;
 use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+use std::io;
 use std::mem;
-use std::thread;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
 
 use plotmux::{plotmux::PlotMux, plotsink::PlotSink};
 
 use crate::{
+    capacity::Slots,
     memory_monitor::memory_monitor,
+    metrics::{metrics_server, MetricsObserver},
     net::Net,
+    observer::{Observers, PlotObserver},
+    partition::{maximal_work_clusters, merge_work_clusters},
     pseudo_state_monitor::pseudo_state_monitor,
+    replay::Replay,
     state::{StateBlockable, StateDelta},
+    thread_pool::{ThreadPool, WorkClusterMaker},
+    trace::{write_trace, Tracer},
     work_cluster::WorkCluster,
-    PlotOptions, ReactorOptions, Token,
+    Checkpoint, CheckpointError, Firing, FirstMatch, Injector, NamedAny, NetError, PlotOptions,
+    ReactorObserver, ReactorOptions, Recorder, Recording, SchedulingPolicy, Subscription, Token,
+    TokenRegistry,
 };
 
+#[derive(Clone)]
+pub struct BuildOptions {
+    pub merge_conflicting_clusters: bool,
+    pub type_check: bool,
+}
+impl Default for BuildOptions {
+    fn default() -> Self {
+        Self {
+            merge_conflicting_clusters: false,
+            type_check: true,
+        }
+    }
+}
+
 pub struct MultiReactor {
     work_clusters: Vec<Box<dyn FnOnce(Receiver<StateBlockable>) -> WorkCluster + Send>>,
+    work_cluster_transitions: Vec<HashSet<String>>,
+    scheduling_policies: Vec<Box<dyn SchedulingPolicy>>,
+    thread_pool: Option<usize>,
+    recorder: Option<Sender<Firing>>,
+    replay: Option<Recording>,
+    observers: Vec<Arc<dyn ReactorObserver>>,
+    place_producers: HashMap<String, HashSet<usize>>,
+    place_consumers: HashMap<String, usize>,
+    external_inputs: Vec<Vec<(String, Receiver<StateBlockable>)>>,
+    external_places: HashMap<String, Arc<()>>,
+    subscriptions: Vec<HashMap<(String, TypeId), Sender<StateBlockable>>>,
     dots: Vec<(String, String)>,
     pseudo_hashes: Vec<u64>,
     start_state: HashMap<(String, TypeId), (i64, &'static str)>,
     state_delta_monitor: Receiver<StateDelta>,
-    pseudo_state_monitor_plot: PlotSink,
+    state_delta_notifier: Sender<StateDelta>,
+    work_cluster_plots: Vec<Arc<Mutex<PlotSink>>>,
+    pseudo_state_monitor_plot: Arc<Mutex<PlotSink>>,
     memory_monitor_plot: PlotSink,
     reactor_plot: PlotSink,
 }
@@ -55,58 +96,184 @@ impl MultiReactor {
         dot += "}";
         graphviz(&dot, hash)
     }
-    pub fn make(mut net: Net, work_clusters: Vec<HashSet<String>>, plotmux: &mut PlotMux) -> Self {
+    pub fn make_auto(net: Net, plotmux: &mut PlotMux) -> Self {
+        Self::make_auto_pinned(net, vec![], plotmux)
+    }
+    pub fn make_auto_pinned(
+        net: Net,
+        pinned: Vec<HashSet<String>>,
+        plotmux: &mut PlotMux,
+    ) -> Self {
+        let work_clusters = maximal_work_clusters(&net, &pinned);
+        Self::make(net, work_clusters, plotmux)
+    }
+    pub fn try_make(
+        net: Net,
+        work_clusters: Vec<HashSet<String>>,
+        plotmux: &mut PlotMux,
+    ) -> Result<Self, Vec<NetError>> {
+        Self::try_make_with_options(net, work_clusters, &BuildOptions::default(), plotmux)
+    }
+    pub fn try_make_with_options(
+        mut net: Net,
+        mut work_clusters: Vec<HashSet<String>>,
+        options: &BuildOptions,
+        plotmux: &mut PlotMux,
+    ) -> Result<Self, Vec<NetError>> {
+        let mut errors = vec![];
+        if let Err(e) = net.validate() {
+            errors.extend(e);
+        }
+        if let Err(e) = net.check_weights() {
+            errors.extend(e);
+        }
+        if let Err(e) = net.check_fault_policies() {
+            errors.extend(e);
+        }
+        if options.merge_conflicting_clusters {
+            work_clusters = merge_work_clusters(&net, &work_clusters);
+        }
+        if let Err(e) = net.validate_work_clusters(&work_clusters) {
+            errors.extend(e);
+        }
+        if options.type_check {
+            if let Err(e) = net.type_check() {
+                errors.extend(e);
+            }
+        }
+        if errors.is_empty() {
+            Self::build(net, work_clusters, plotmux)
+        } else {
+            Err(errors)
+        }
+    }
+    pub fn make(net: Net, work_clusters: Vec<HashSet<String>>, plotmux: &mut PlotMux) -> Self {
+        Self::make_with_options(net, work_clusters, &BuildOptions::default(), plotmux)
+    }
+    pub fn make_with_options(
+        net: Net,
+        work_clusters: Vec<HashSet<String>>,
+        options: &BuildOptions,
+        plotmux: &mut PlotMux,
+    ) -> Self {
+        match Self::try_make_with_options(net, work_clusters, options, plotmux) {
+            Ok(reactor) => reactor,
+            Err(errors) => panic!(
+                "invalid net:\n{}",
+                errors.iter().map(|e| e.to_string()).join("\n")
+            ),
+        }
+    }
+    fn build(
+        mut net: Net,
+        work_clusters: Vec<HashSet<String>>,
+        plotmux: &mut PlotMux,
+    ) -> Result<Self, Vec<NetError>> {
+        let cluster_of = |t_name: &String| work_clusters.iter().position(|ts| ts.contains(t_name));
+        let mut place_producers: HashMap<String, HashSet<usize>> = HashMap::new();
+        for (t_name, p_name) in net.tp_edges.keys() {
+            if let Some(cluster_idx) = cluster_of(t_name) {
+                place_producers
+                    .entry(p_name.clone())
+                    .or_default()
+                    .insert(cluster_idx);
+            }
+        }
+        for (p_name, ts) in net.fault_routes() {
+            for t_name in &ts {
+                if let Some(cluster_idx) = cluster_of(t_name) {
+                    place_producers
+                        .entry(p_name.clone())
+                        .or_default()
+                        .insert(cluster_idx);
+                }
+            }
+        }
+        let place_consumers: HashMap<String, usize> = net
+            .place_readers()
+            .iter()
+            .filter_map(|(p_name, ts)| {
+                ts.iter()
+                    .next()
+                    .and_then(&cluster_of)
+                    .map(|cluster_idx| (p_name.clone(), cluster_idx))
+            })
+            .collect();
+        let mut errors = vec![];
         let place_io_clusters: HashMap<String, (HashSet<usize>, usize)> = {
             let mut place_io_clusters: HashMap<String, (HashSet<usize>, HashSet<usize>)> = net
                 .places
-                .iter()
-                .map(|(p_name, _)| (p_name.clone(), (HashSet::new(), HashSet::new())))
+                .keys()
+                .map(|p_name| (p_name.clone(), (HashSet::new(), HashSet::new())))
                 .collect::<_>();
+            let mut unclustered = BTreeSet::new();
             for (t_name, places) in &net.transition_to_places {
-                let cluster_idx = work_clusters
-                    .iter()
-                    .position(|ts| ts.contains(t_name))
-                    .unwrap();
-                for place in places {
-                    if let Some(io_clusters) = place_io_clusters.get_mut(place) {
-                        io_clusters.0.insert(cluster_idx);
-                    } else {
-                        panic!("place {:?} not in {:?}", place, place_io_clusters)
+                let cluster_idx = match cluster_of(t_name) {
+                    Some(cluster_idx) => cluster_idx,
+                    None => {
+                        unclustered.insert(t_name.clone());
+                        continue;
                     }
+                };
+                for place in places {
+                    place_io_clusters
+                        .entry(place.clone())
+                        .or_default()
+                        .0
+                        .insert(cluster_idx);
                 }
             }
-            for (p_name, transitions) in &net.place_to_transitions {
+            for (p_name, producers) in &place_producers {
+                place_io_clusters
+                    .entry(p_name.clone())
+                    .or_default()
+                    .0
+                    .extend(producers);
+            }
+            for (p_name, transitions) in &net.place_readers() {
                 for t_name in transitions {
-                    let cluster_idx = work_clusters
-                        .iter()
-                        .position(|ts| ts.contains(t_name))
-                        .expect(&format!("transition: '{}' is not present in any work clusters {:?}", t_name, work_clusters));
+                    let cluster_idx = match cluster_of(t_name) {
+                        Some(cluster_idx) => cluster_idx,
+                        None => {
+                            unclustered.insert(t_name.clone());
+                            continue;
+                        }
+                    };
                     place_io_clusters
-                        .get_mut(p_name)
-                        .unwrap()
+                        .entry(p_name.clone())
+                        .or_default()
                         .1
                         .insert(cluster_idx);
                 }
             }
+            errors.extend(
+                unclustered
+                    .into_iter()
+                    .map(|transition| NetError::TransitionNotInWorkCluster { transition }),
+            );
             for (place, (in_clusters, out_clusters)) in place_io_clusters.iter_mut() {
-                if in_clusters.len() != 0 && out_clusters.len() == 0 {
-                    out_clusters.insert(in_clusters.iter().next().unwrap().clone());
-                } else if in_clusters.len() == 0 && out_clusters.len() != 0 {
-                    in_clusters.insert(out_clusters.iter().next().unwrap().clone());
+                if !in_clusters.is_empty() && out_clusters.is_empty() {
+                    out_clusters.insert(*in_clusters.iter().next().unwrap());
+                } else if in_clusters.is_empty() && !out_clusters.is_empty() {
+                    in_clusters.insert(*out_clusters.iter().next().unwrap());
+                }
+                if out_clusters.is_empty() || in_clusters.is_empty() {
+                    errors.push(NetError::IsolatedPlace {
+                        place: place.clone(),
+                    });
+                } else if out_clusters.len() > 1 {
+                    errors.push(NetError::PlaceConsumedByMultipleWorkClusters {
+                        place: place.clone(),
+                        clusters: out_clusters.iter().cloned().sorted().collect(),
+                    });
                 }
-                assert!(
-                    out_clusters.len() != 0 && in_clusters.len() != 0,
-                    "{} has 0 output clusters: {:#?} and 0 input clusters: {:#?}",
-                    place,
-                    out_clusters,
-                    in_clusters,
-                );
+            }
+            if !errors.is_empty() {
+                return Err(errors);
             }
             place_io_clusters
                 .into_iter()
-                .map(|(p_name, (in_c, out_c))| {
-                    (p_name, (in_c, out_c.into_iter().reduce(|_, x| x).unwrap()))
-                })
+                .map(|(p_name, (in_c, out_c))| (p_name, (in_c, out_c.into_iter().next().unwrap())))
                 .collect()
         };
         let mut contained_places: HashMap<usize, HashSet<String>> = HashMap::new();
@@ -133,6 +300,25 @@ impl MultiReactor {
             })
             .collect::<HashMap<String, (HashMap<usize, Sender<_>>, Option<(usize, Receiver<_>)>)>>(
             );
+        let wakes = work_clusters
+            .iter()
+            .map(|_| unbounded())
+            .collect::<Vec<(Sender<_>, Receiver<_>)>>();
+        let slots: HashMap<String, Slots> = net
+            .capacities
+            .iter()
+            .map(|(p_name, (capacity, _))| {
+                let (tx, rx) = bounded(*capacity);
+                let notify = place_producers
+                    .get(p_name)
+                    .into_iter()
+                    .flatten()
+                    .filter(|idx| place_consumers.get(p_name) != Some(idx))
+                    .map(|idx| wakes[*idx].0.clone())
+                    .collect();
+                (p_name.clone(), Slots { tx, rx, notify })
+            })
+            .collect();
         let mut dots = vec![];
         let mut pseudo_hashes = vec![];
         let (state_delta_notifier, state_delta_monitor) = unbounded();
@@ -141,7 +327,15 @@ impl MultiReactor {
             .into_iter()
             .map(|((p, ty), (s, n))| ((p, ty), (s as i64, n)))
             .collect();
-        Self {
+        let work_cluster_plots = work_clusters
+            .iter()
+            .map(|cluster| {
+                Arc::new(Mutex::new(
+                    plotmux.add_plot_sink(&format!("reactor/work_cluster/{:?}", cluster)),
+                ))
+            })
+            .collect::<Vec<_>>();
+        Ok(Self {
             work_clusters: work_clusters
                 .iter()
                 .enumerate()
@@ -164,7 +358,7 @@ impl MultiReactor {
                                     return Some(p_name.clone());
                                 }
                             }
-                            return None;
+                            None
                         })
                         .collect();
                     let contained_places = contained_places
@@ -172,7 +366,7 @@ impl MultiReactor {
                         .filter_map(|(k, v)| if *k == i { Some(v) } else { None })
                         .fold(HashSet::new(), |acc, x| acc.union(x).cloned().collect());
                     let net_split =
-                        net.split(&cluster, &input_places, &output_places, &contained_places);
+                        net.split(cluster, &input_places, &output_places, &contained_places);
                     let input_places = input_places
                         .iter()
                         .map(|p| {
@@ -191,101 +385,446 @@ impl MultiReactor {
                             )
                         })
                         .collect();
-                    dots.push(net_split.as_dot(true));
+                    let slots = net_split
+                        .capacities
+                        .keys()
+                        .map(|p| (p.clone(), slots[p].clone()))
+                        .collect::<HashMap<_, _>>();
+                    dots.push(net_split.as_dot_in(true, &format!("{}_", i)));
                     pseudo_hashes.push(net_split.pseudo_hash());
-                    let plotsink =
-                        plotmux.add_plot_sink(&format!("reactor/work_cluster/{:?}", cluster));
+                    let plotsink = work_cluster_plots[i].clone();
                     let sdn = state_delta_notifier.clone();
+                    let wake = wakes[i].clone();
                     let f: Box<dyn FnOnce(Receiver<StateBlockable>) -> WorkCluster + Send> =
                         Box::new(move |exit_rx| {
                             WorkCluster::make(
                                 net_split,
                                 input_places,
                                 output_places,
+                                slots,
                                 plotsink,
                                 sdn,
+                                wake,
                                 exit_rx,
                             )
                         });
                     f
                 })
                 .collect(),
-            dots: dots,
-            pseudo_hashes: pseudo_hashes,
-            start_state: start_state,
-            state_delta_monitor: state_delta_monitor,
-            pseudo_state_monitor_plot: plotmux.add_plot_sink("reactor/monitor/pseudo_state"),
+            scheduling_policies: work_clusters
+                .iter()
+                .map(|_| {
+                    let p: Box<dyn SchedulingPolicy> = Box::new(FirstMatch::make());
+                    p
+                })
+                .collect(),
+            thread_pool: None,
+            recorder: None,
+            replay: None,
+            observers: vec![],
+            external_inputs: work_clusters.iter().map(|_| vec![]).collect(),
+            external_places: HashMap::new(),
+            subscriptions: work_clusters.iter().map(|_| HashMap::new()).collect(),
+            place_producers,
+            place_consumers,
+            work_cluster_transitions: work_clusters,
+            dots,
+            pseudo_hashes,
+            start_state,
+            state_delta_monitor,
+            state_delta_notifier,
+            work_cluster_plots,
+            pseudo_state_monitor_plot: Arc::new(Mutex::new(
+                plotmux.add_plot_sink("reactor/monitor/pseudo_state"),
+            )),
             memory_monitor_plot: plotmux.add_plot_sink("reactor/monitor/memory"),
             reactor_plot: plotmux.add_plot_sink("reactor"),
+        })
+    }
+    pub fn work_clusters(&self) -> &Vec<HashSet<String>> {
+        &self.work_cluster_transitions
+    }
+    pub fn set_scheduling_policy(
+        &mut self,
+        work_cluster: usize,
+        policy: Box<dyn SchedulingPolicy>,
+    ) {
+        self.scheduling_policies[work_cluster] = policy;
+    }
+    pub fn set_thread_pool(&mut self, threads: usize) {
+        self.thread_pool = Some(threads);
+    }
+    pub fn add_observer(&mut self, observer: Arc<dyn ReactorObserver>) {
+        self.observers.push(observer);
+    }
+    pub fn record(&mut self) -> Recorder {
+        let (tx, rx) = unbounded();
+        self.recorder = Some(tx);
+        Recorder::make(rx)
+    }
+    pub fn set_replay(&mut self, recording: Recording) {
+        if let Some(f) = recording
+            .firings
+            .iter()
+            .find(|f| f.work_cluster >= self.work_clusters.len())
+        {
+            panic!(
+                "replay firing of {} in work cluster {} which does not exist",
+                f.transition, f.work_cluster
+            );
         }
+        self.replay = Some(recording);
     }
-    pub fn run(
+    pub fn injector<T: NamedAny + Send>(&mut self, place: &str) -> Injector<T> {
+        let cluster_idx = *self
+            .place_consumers
+            .get(place)
+            .unwrap_or_else(|| panic!("place '{}' is not consumed by any transition", place));
+        let (tx, rx) = unbounded();
+        self.external_inputs[cluster_idx].push((place.into(), rx));
+        let alive = self
+            .external_places
+            .entry(place.into())
+            .or_insert(Arc::new(()))
+            .clone();
+        Injector::make(place.into(), tx, self.state_delta_notifier.clone(), alive)
+    }
+    pub fn subscribe<T: 'static>(&mut self, place: &str) -> Subscription<T> {
+        assert!(
+            !self.place_consumers.contains_key(place),
+            "place '{}' is consumed by a transition and can not be subscribed to",
+            place
+        );
+        let producers = self
+            .place_producers
+            .get(place)
+            .unwrap_or_else(|| panic!("place '{}' is not produced by any transition", place));
+        let (tx, rx) = unbounded();
+        for cluster_idx in producers {
+            self.subscriptions[*cluster_idx].insert((place.into(), TypeId::of::<T>()), tx.clone());
+        }
+        Subscription::make(rx)
+    }
+    fn serve_metrics(&mut self, plot_options: &PlotOptions) -> io::Result<Option<impl Drop>> {
+        plot_options
+            .metrics
+            .map(|addr| {
+                let metrics = Arc::new(MetricsObserver::make());
+                self.observers.push(metrics.clone());
+                metrics_server(addr, metrics)
+            })
+            .transpose()
+    }
+    pub fn try_run(
         mut self,
         plot_options: &Option<ReactorOptions>,
+    ) -> io::Result<HashMap<String, HashMap<TypeId, VecDeque<Token>>>> {
+        let plot_options: PlotOptions = plot_options.into();
+        let metrics = self.serve_metrics(&plot_options)?;
+        let (exit_txs, exit_rxs) = (0..self.work_clusters.len()).map(|_| unbounded()).unzip();
+        Ok(self.run_with(plot_options, metrics, exit_txs, exit_rxs))
+    }
+    pub fn run(
+        self,
+        plot_options: &Option<ReactorOptions>,
     ) -> HashMap<String, HashMap<TypeId, VecDeque<Token>>> {
+        self.try_run(plot_options)
+            .unwrap_or_else(|e| panic!("unable to start metrics server: {}", e))
+    }
+    pub fn try_spawn(mut self, plot_options: &Option<ReactorOptions>) -> io::Result<ReactorHandle> {
         let plot_options: PlotOptions = plot_options.into();
+        let metrics = self.serve_metrics(&plot_options)?;
+        let (exit_txs, exit_rxs): (Vec<_>, Vec<_>) =
+            (0..self.work_clusters.len()).map(|_| unbounded()).unzip();
+        let control_txs = exit_txs.clone();
+        Ok(ReactorHandle::make(
+            control_txs,
+            thread::Builder::new()
+                .name("reactor".into())
+                .spawn(move || self.run_with(plot_options, metrics, exit_txs, exit_rxs))?,
+        ))
+    }
+    pub fn spawn(self, plot_options: &Option<ReactorOptions>) -> ReactorHandle {
+        self.try_spawn(plot_options)
+            .unwrap_or_else(|e| panic!("unable to spawn reactor: {}", e))
+    }
+    fn run_with(
+        mut self,
+        plot_options: PlotOptions,
+        metrics: Option<impl Drop>,
+        exit_txs: Vec<Sender<StateBlockable>>,
+        exit_rxs: Vec<Receiver<StateBlockable>>,
+    ) -> HashMap<String, HashMap<TypeId, VecDeque<Token>>> {
+        drop(self.state_delta_notifier);
+        let work_cluster_count = self.work_clusters.len();
         let mut threads = vec![];
-        let mut exit_txs = vec![];
-        let (nonblocking_sender, nonblocking_receiver) = bounded(self.work_clusters.len());
-        for (i, wc) in self.work_clusters.into_iter().enumerate() {
-            let (exit_tx, exit_rx) = bounded(1);
-            exit_txs.push(exit_tx);
-            let po = plot_options.clone();
+        let mut pooled = vec![];
+        let (nonblocking_sender, nonblocking_receiver) = bounded(work_cluster_count);
+        let (done_tx, done_rx) = bounded::<()>(0);
+        let replay = self
+            .replay
+            .take()
+            .map(|recording| Arc::new(Replay::make(recording, exit_txs.clone())));
+        let mut observers = mem::take(&mut self.observers);
+        if plot_options.local_state
+            || plot_options.reactor_timing
+            || plot_options.transition_timing
+            || plot_options.pseudo_state
+            || plot_options.memory_profile.is_some()
+        {
+            observers.insert(
+                0,
+                Arc::new(PlotObserver::make(
+                    plot_options.clone(),
+                    self.work_cluster_plots,
+                    self.pseudo_state_monitor_plot.clone(),
+                    self.memory_monitor_plot,
+                )),
+            );
+        }
+        let observers = Observers::make(observers);
+        let (trace_tx, trace_rx) = unbounded();
+        let trace = plot_options.trace.clone();
+        let tracer = trace.as_ref().map(|_| Tracer::make(trace_tx));
+        for (i, ((((wc, policy), exit_rx), external_inputs), subscriptions)) in self
+            .work_clusters
+            .into_iter()
+            .zip(self.scheduling_policies)
+            .zip(exit_rxs)
+            .zip(self.external_inputs)
+            .zip(self.subscriptions)
+            .enumerate()
+        {
+            let recorder = self.recorder.clone();
+            let replay = replay.clone();
+            let observers = observers.clone();
+            let tracer = tracer
+                .as_ref()
+                .map(|tracer| tracer.thread(i, &format!("work-cluster-{}", i)));
+            let make_wc = move || {
+                let mut wc = wc(exit_rx);
+                wc.set_scheduling_policy(policy);
+                wc.observe(i, observers);
+                if let Some(tx) = recorder {
+                    wc.record(i, tx);
+                }
+                if let Some(replay) = replay {
+                    wc.replay(i, replay);
+                }
+                if let Some(tracer) = tracer {
+                    wc.trace(tracer);
+                }
+                for (place, rx) in external_inputs {
+                    wc.add_input(place, rx);
+                }
+                for (p_ty, tx) in subscriptions {
+                    wc.subscribe(p_ty, tx);
+                }
+                wc
+            };
             let nbs = nonblocking_sender.clone();
+            if self.thread_pool.is_some() {
+                let make_pooled: WorkClusterMaker = Box::new(move || {
+                    let mut wc = make_wc();
+                    wc.disable_blocking();
+                    nbs.send(wc.nonblocking_states()).unwrap();
+                    wc
+                });
+                pooled.push(make_pooled);
+                continue;
+            }
             threads.push(
                 thread::Builder::new()
                     .name(format!("work-cluster-{}", i))
                     .spawn(move || {
-                        let wc = wc(exit_rx);
+                        let wc = make_wc();
                         nbs.send(wc.nonblocking_states()).unwrap();
-                        wc.run(po)
+                        wc.run()
                     })
-                    .expect(&format!("unable to spawn work-cluster-{} thread", i)),
+                    .unwrap_or_else(|_| panic!("unable to spawn work-cluster-{} thread", i)),
             );
         }
-        let memory_monitor_thread = if let Some(period) = plot_options.memory_profile {
-            Some(memory_monitor(period, self.memory_monitor_plot))
-        } else {
-            None
+        let pool = self
+            .thread_pool
+            .map(|pool_threads| ThreadPool::make(pooled, pool_threads));
+        let memory_period = match (plot_options.memory_profile, plot_options.metrics) {
+            (Some(period), _) => Some(period),
+            (None, Some(_)) => Some(1.0),
+            (None, None) => None,
         };
+        let memory_monitor_thread =
+            memory_period.map(|period| memory_monitor(period, observers.clone()));
         let pseudo_state_monitor_thread = pseudo_state_monitor(
             self.start_state,
-            (0..threads.len())
-                .map(|_| nonblocking_receiver.recv().unwrap())
-                .into_iter()
-                .flatten()
-                .collect::<HashSet<BTreeSet<_>>>(),
+            (0..work_cluster_count)
+                .flat_map(|_| nonblocking_receiver.recv().unwrap())
+                .collect::<HashSet<_>>(),
+            self.external_places,
             self.state_delta_monitor,
+            done_rx,
             exit_txs,
+            observers,
             self.pseudo_state_monitor_plot,
-            plot_options,
+            plot_options.clone(),
         );
-        let end_state = threads
+        let states = match pool {
+            Some(pool) => pool.run(),
+            None => threads.into_iter().map(|t| t.join().ok()).collect(),
+        };
+        let end_state = states
             .into_iter()
             .enumerate()
-            .fold(HashMap::new(), |mut acc, (i, t)| {
-                match t.join() {
-                    Ok(state) => {
+            .fold(HashMap::new(), |mut acc, (i, state)| {
+                match state {
+                    Some(state) => {
                         for (k, v) in state.into_iter().filter(|(_place, vecs)| {
-                            for (_ty, vec) in vecs {
-                                if vec.len() > 0 {
+                            for vec in vecs.values() {
+                                if !vec.is_empty() {
                                     return true;
                                 }
                             }
                             false
                         }) {
-                            acc.insert(k, v);
+                            let place: &mut HashMap<TypeId, VecDeque<Token>> =
+                                acc.entry(k).or_default();
+                            for (ty, vec) in v {
+                                place.entry(ty).or_default().extend(vec);
+                            }
                         }
                     }
-                    Err(_) => {
+                    None => {
                         self.reactor_plot
                             .println(&format!("failed to join work-cluster-{}", i));
                     }
                 }
                 acc
             });
+        if let Some(path) = &trace {
+            drop(tracer);
+            if let Err(e) = write_trace(path, trace_rx) {
+                self.reactor_plot
+                    .println(&format!("unable to write trace {:?}: {}", path, e));
+            }
+        }
+        drop(done_tx);
         drop(pseudo_state_monitor_thread);
         drop(memory_monitor_thread);
+        drop(metrics);
         end_state
     }
 }
+
+pub struct ReactorHandle {
+    control_txs: Vec<Sender<StateBlockable>>,
+    paused: Mutex<Vec<bool>>,
+    thread: JoinHandle<HashMap<String, HashMap<TypeId, VecDeque<Token>>>>,
+}
+impl ReactorHandle {
+    pub(crate) fn make(
+        control_txs: Vec<Sender<StateBlockable>>,
+        thread: JoinHandle<HashMap<String, HashMap<TypeId, VecDeque<Token>>>>,
+    ) -> Self {
+        Self {
+            paused: Mutex::new(vec![false; control_txs.len()]),
+            control_txs,
+            thread,
+        }
+    }
+    fn send(&self, work_cluster: usize, message: StateBlockable) {
+        let _ = self.control_txs[work_cluster].send(message);
+    }
+    pub fn shutdown(&self) {
+        for i in 0..self.control_txs.len() {
+            self.send(i, StateBlockable::Terminate(()));
+        }
+    }
+    pub fn pause(&self) {
+        for i in 0..self.control_txs.len() {
+            self.pause_work_cluster(i);
+        }
+    }
+    pub fn resume(&self) {
+        for i in 0..self.control_txs.len() {
+            self.resume_work_cluster(i);
+        }
+    }
+    pub fn pause_work_cluster(&self, work_cluster: usize) {
+        self.paused.lock().unwrap()[work_cluster] = true;
+        self.send(work_cluster, StateBlockable::Pause(()));
+    }
+    pub fn resume_work_cluster(&self, work_cluster: usize) {
+        self.paused.lock().unwrap()[work_cluster] = false;
+        self.send(work_cluster, StateBlockable::Resume(()));
+    }
+    pub fn checkpoint(
+        &self,
+        registry: &Arc<TokenRegistry>,
+        timeout: Duration,
+    ) -> Result<Checkpoint, CheckpointError> {
+        let deadline = Instant::now() + timeout;
+        let paused = self.paused.lock().unwrap().clone();
+        self.pause();
+        let barriers = (0..self.control_txs.len())
+            .map(|i| {
+                let (tx, rx) = bounded(1);
+                self.send(i, StateBlockable::Barrier(tx));
+                rx
+            })
+            .collect::<Vec<_>>();
+        let mut checkpoint = Ok(Checkpoint::make());
+        for (i, rx) in barriers.into_iter().enumerate() {
+            match rx.recv_deadline(deadline) {
+                Ok(()) => {}
+                Err(RecvTimeoutError::Timeout) => {
+                    checkpoint = Err(CheckpointError::Timeout(timeout))
+                }
+                Err(RecvTimeoutError::Disconnected) => {
+                    checkpoint = checkpoint.and(Err(CheckpointError::Finished(i)))
+                }
+            }
+        }
+        if checkpoint.is_ok() {
+            let replies = (0..self.control_txs.len())
+                .map(|i| {
+                    let (tx, rx) = bounded(1);
+                    self.send(i, StateBlockable::Checkpoint((registry.clone(), tx)));
+                    rx
+                })
+                .collect::<Vec<_>>();
+            for (i, rx) in replies.into_iter().enumerate() {
+                if let Ok(c) = checkpoint.as_mut() {
+                    match rx.recv_deadline(deadline) {
+                        Ok(Ok(other)) => c.merge(other),
+                        Ok(Err(e)) => checkpoint = Err(e),
+                        Err(RecvTimeoutError::Timeout) => {
+                            checkpoint = Err(CheckpointError::Timeout(timeout))
+                        }
+                        Err(RecvTimeoutError::Disconnected) => {
+                            checkpoint = Err(CheckpointError::Finished(i))
+                        }
+                    }
+                }
+            }
+        }
+        for (i, paused) in paused.into_iter().enumerate() {
+            if !paused {
+                self.resume_work_cluster(i);
+            }
+        }
+        checkpoint
+    }
+    pub fn checkpoint_to(
+        &self,
+        path: &Path,
+        registry: &Arc<TokenRegistry>,
+        timeout: Duration,
+    ) -> Result<(), CheckpointError> {
+        self.checkpoint(registry, timeout)?.write(path)
+    }
+    pub fn is_finished(&self) -> bool {
+        self.thread.is_finished()
+    }
+    pub fn join(self) -> HashMap<String, HashMap<TypeId, VecDeque<Token>>> {
+        self.thread.join().expect("unable to join reactor thread")
+    }
+}
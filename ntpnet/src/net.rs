@@ -1,14 +1,19 @@
 use itertools::Itertools;
 use std::any::TypeId;
-use std::collections::{hash_map::DefaultHasher, HashMap, HashSet, VecDeque};
+use std::collections::{hash_map::DefaultHasher, BTreeMap, HashMap, HashSet, VecDeque};
 use std::env;
 use std::hash::{Hash, Hasher};
 use std::io::Write;
 use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::time::Duration;
 use tempfile::NamedTempFile;
 
-use crate::{NamedAny, Token, TransitionMaker};
+use crate::{
+    transition::{Describe, Description, Transition},
+    Backpressure, Checkpoint, CheckpointError, FaultPolicy, NetError, QueueDiscipline, Token,
+    TokenRegistry, TransitionMaker,
+};
 pub struct Net {
     pub transitions: HashMap<String, TransitionMaker>,
     pub places: HashMap<String, HashMap<TypeId, VecDeque<Token>>>,
@@ -16,6 +21,18 @@ pub struct Net {
     pub place_to_transitions: HashMap<String, HashSet<String>>,
     pub pt_edges: HashMap<(String, String), String>,
     pub tp_edges: HashMap<(String, String), String>,
+    pub pt_weights: HashMap<(String, String), usize>,
+    pub tp_weights: HashMap<(String, String), usize>,
+    pub inhibitors: HashSet<(String, String)>,
+    pub priorities: HashMap<String, i32>,
+    pub timers: HashMap<String, Duration>,
+    pub capacities: HashMap<String, (usize, Backpressure)>,
+    pub disciplines: HashMap<String, QueueDiscipline>,
+    pub delays: HashMap<String, Duration>,
+    pub fault_policies: HashMap<String, FaultPolicy>,
+    pub descriptions: HashMap<String, Description>,
+    pub subnets: BTreeMap<String, HashSet<String>>,
+    pub external_places: HashSet<String>,
 }
 impl Net {
     pub fn make() -> Self {
@@ -26,6 +43,18 @@ impl Net {
             place_to_transitions: HashMap::new(),
             pt_edges: HashMap::new(),
             tp_edges: HashMap::new(),
+            pt_weights: HashMap::new(),
+            tp_weights: HashMap::new(),
+            inhibitors: HashSet::new(),
+            priorities: HashMap::new(),
+            timers: HashMap::new(),
+            capacities: HashMap::new(),
+            disciplines: HashMap::new(),
+            delays: HashMap::new(),
+            fault_policies: HashMap::new(),
+            descriptions: HashMap::new(),
+            subnets: BTreeMap::new(),
+            external_places: HashSet::new(),
         }
     }
     pub fn split(
@@ -36,10 +65,23 @@ impl Net {
         contained_places: &HashSet<String>,
     ) -> Self {
         let mut right = Self::make();
+        right.subnets = self.subnets.clone();
         for t_name in transitions {
             right
                 .transitions
                 .insert(t_name.clone(), self.transitions.remove(t_name).unwrap());
+            if let Some(d) = self.descriptions.remove(t_name) {
+                right.descriptions.insert(t_name.clone(), d);
+            }
+            if let Some(priority) = self.priorities.remove(t_name) {
+                right.priorities.insert(t_name.clone(), priority);
+            }
+            if let Some(delay) = self.delays.remove(t_name) {
+                right.delays.insert(t_name.clone(), delay);
+            }
+            if let Some(policy) = self.fault_policies.remove(t_name) {
+                right.fault_policies.insert(t_name.clone(), policy);
+            }
             right.transition_to_places.insert(
                 t_name.clone(),
                 self.transition_to_places.remove(t_name).unwrap(),
@@ -47,13 +89,30 @@ impl Net {
             for p_name in &right.transition_to_places[t_name] {
                 let id = (t_name.clone(), p_name.clone());
                 let edge = self.tp_edges.remove(&id).unwrap();
+                if let Some(w) = self.tp_weights.remove(&id) {
+                    right.tp_weights.insert(id.clone(), w);
+                }
                 right.tp_edges.insert(id, edge);
             }
         }
+        for (p_name, t_name) in self
+            .inhibitors
+            .iter()
+            .filter(|(_, t)| transitions.contains(t))
+            .cloned()
+            .collect::<Vec<_>>()
+        {
+            right
+                .inhibitors
+                .insert(self.inhibitors.take(&(p_name, t_name)).unwrap());
+        }
         for p_name in contained_places {
             right
                 .places
                 .insert(p_name.clone(), self.places.remove(p_name).unwrap());
+            if let Some(period) = self.timers.remove(p_name) {
+                right.timers.insert(p_name.clone(), period);
+            }
             right.place_to_transitions.insert(
                 p_name.clone(),
                 self.place_to_transitions.remove(p_name).unwrap(),
@@ -61,6 +120,9 @@ impl Net {
             for t_name in &right.place_to_transitions[p_name] {
                 let id = (p_name.clone(), t_name.clone());
                 let edge = self.pt_edges.remove(&id).unwrap();
+                if let Some(w) = self.pt_weights.remove(&id) {
+                    right.pt_weights.insert(id.clone(), w);
+                }
                 right.pt_edges.insert(id, edge);
             }
         }
@@ -68,6 +130,9 @@ impl Net {
             right
                 .places
                 .insert(p_name.clone(), self.places.remove(p_name).unwrap());
+            if let Some(period) = self.timers.remove(p_name) {
+                right.timers.insert(p_name.clone(), period);
+            }
             let intersecting_transitions = self.place_to_transitions[p_name]
                 .intersection(transitions)
                 .cloned()
@@ -82,22 +147,97 @@ impl Net {
             for t_name in &right.place_to_transitions[p_name] {
                 let id = (p_name.clone(), t_name.clone());
                 let edge = self.pt_edges.remove(&id).unwrap();
+                if let Some(w) = self.pt_weights.remove(&id) {
+                    right.pt_weights.insert(id.clone(), w);
+                }
                 right.pt_edges.insert(id, edge);
             }
         }
         for p_name in output_places {
-            right = right.add_place(&p_name);
+            right = right.add_place(p_name);
+        }
+        for p_name in contained_places
+            .iter()
+            .chain(input_places)
+            .chain(output_places)
+        {
+            if let Some(capacity) = self.capacities.get(p_name) {
+                right.capacities.insert(p_name.clone(), *capacity);
+            }
+            if let Some(discipline) = self.disciplines.get(p_name) {
+                right.disciplines.insert(p_name.clone(), *discipline);
+            }
         }
         right
     }
     pub fn add_transition(mut self, name: &str, t: TransitionMaker) -> Self {
         self.transitions.insert(name.into(), t);
+        self.descriptions.remove(name);
         if !self.transition_to_places.contains_key(name) {
             self.transition_to_places
                 .insert(name.into(), HashSet::new());
         }
         self
     }
+    pub fn add_typed_transition<T, F>(self, name: &str, t: F) -> Self
+    where
+        T: Transition + Describe + 'static,
+        F: FnOnce() -> T + Send + 'static,
+    {
+        let mut net = self.add_transition(name, Box::new(move || Box::new(t())));
+        net.descriptions.insert(name.into(), T::describe());
+        net
+    }
+    pub fn set_priority(mut self, transition: &str, priority: i32) -> Self {
+        self.priorities.insert(transition.into(), priority);
+        self
+    }
+    pub fn set_delay(mut self, transition: &str, delay: Duration) -> Self {
+        self.delays.insert(transition.into(), delay);
+        self
+    }
+    pub fn set_fault_policy(mut self, transition: &str, policy: FaultPolicy) -> Self {
+        if let FaultPolicy::RouteTo(place) = &policy {
+            self = self.add_place(place);
+        }
+        self.fault_policies.insert(transition.into(), policy);
+        self
+    }
+    pub fn fault_routes(&self) -> HashMap<String, HashSet<String>> {
+        let mut routes: HashMap<String, HashSet<String>> = HashMap::new();
+        for (t, policy) in &self.fault_policies {
+            if let FaultPolicy::RouteTo(place) = policy {
+                routes.entry(place.clone()).or_default().insert(t.clone());
+            }
+        }
+        routes
+    }
+    pub fn add_timer(mut self, place: &str, period: Duration) -> Self {
+        self = self.add_place(place);
+        self.timers.insert(place.into(), period);
+        self
+    }
+    pub fn set_capacity(
+        mut self,
+        place: &str,
+        capacity: usize,
+        backpressure: Backpressure,
+    ) -> Self {
+        assert!(
+            capacity > 0,
+            "place '{}' must have a capacity of at least 1",
+            place
+        );
+        self = self.add_place(place);
+        self.capacities
+            .insert(place.into(), (capacity, backpressure));
+        self
+    }
+    pub fn set_queue_discipline(mut self, place: &str, discipline: QueueDiscipline) -> Self {
+        self = self.add_place(place);
+        self.disciplines.insert(place.into(), discipline);
+        self
+    }
     pub fn add_place(mut self, name: &str) -> Self {
         if !self.places.contains_key(name) {
             self.places.insert(name.into(), HashMap::new());
@@ -108,22 +248,40 @@ impl Net {
         }
         self
     }
+    pub fn add_external_place(mut self, name: &str) -> Self {
+        self.external_places.insert(name.into());
+        self.add_place(name)
+    }
     pub fn set_start_tokens(mut self, place: &str, start_tokens: Vec<Token>) -> Self {
         if let Some(p) = self.places.get_mut(place) {
             for t in start_tokens.into_iter() {
-                let ty = (&*t).type_id();
-                if !p.contains_key(&ty) {
-                    p.insert(ty.clone(), VecDeque::new());
-                }
-                p.get_mut(&ty).unwrap().push_back(t);
+                let ty = (*t).type_id();
+                p.entry(ty).or_default().push_back(t);
             }
         } else {
-            self = self
-                .add_place(place.into())
-                .set_start_tokens(place, start_tokens);
+            self = self.add_place(place).set_start_tokens(place, start_tokens);
         }
         self
     }
+    pub fn try_set_start_tokens_from_checkpoint(
+        mut self,
+        checkpoint: &Path,
+        registry: &TokenRegistry,
+    ) -> Result<Self, CheckpointError> {
+        let places = Checkpoint::read(checkpoint)?.into_tokens(registry)?;
+        for (place, start_tokens) in places {
+            self = self.set_start_tokens(&place, start_tokens);
+        }
+        Ok(self)
+    }
+    pub fn set_start_tokens_from_checkpoint(
+        self,
+        checkpoint: &Path,
+        registry: &TokenRegistry,
+    ) -> Self {
+        self.try_set_start_tokens_from_checkpoint(checkpoint, registry)
+            .unwrap_or_else(|e| panic!("unable to restore {:?}: {}", checkpoint, e))
+    }
     pub fn place_to_transition(mut self, place: &str, edge: &str, transition: &str) -> Self {
         if let Some(s) = self.place_to_transitions.get_mut(place) {
             s.insert(transition.into());
@@ -142,6 +300,18 @@ impl Net {
             .insert((place.into(), transition.into()), edge.into());
         self
     }
+    pub fn place_to_transition_weighted(
+        mut self,
+        place: &str,
+        edge: &str,
+        transition: &str,
+        weight: usize,
+    ) -> Self {
+        self = self.place_to_transition(place, edge, transition);
+        self.pt_weights
+            .insert((place.into(), transition.into()), weight);
+        self
+    }
     pub fn transition_to_place(mut self, transition: &str, edge: &str, place: &str) -> Self {
         if !self.places.contains_key(place) {
             self = self.add_place(place);
@@ -160,6 +330,228 @@ impl Net {
             .insert((transition.into(), place.into()), edge.into());
         self
     }
+    pub fn transition_to_place_weighted(
+        mut self,
+        transition: &str,
+        edge: &str,
+        place: &str,
+        weight: usize,
+    ) -> Self {
+        self = self.transition_to_place(transition, edge, place);
+        self.tp_weights
+            .insert((transition.into(), place.into()), weight);
+        self
+    }
+    pub fn inhibit(mut self, place: &str, transition: &str) -> Self {
+        if !self.places.contains_key(place) {
+            self = self.add_place(place);
+        }
+        self.inhibitors.insert((place.into(), transition.into()));
+        self
+    }
+    pub fn place_readers(&self) -> HashMap<String, HashSet<String>> {
+        let mut readers = self.place_to_transitions.clone();
+        for (p, t) in &self.inhibitors {
+            readers.entry(p.clone()).or_default().insert(t.clone());
+        }
+        readers
+    }
+    pub fn try_embed(
+        self,
+        prefix: &str,
+        subnet: Net,
+        port_map: HashMap<String, String>,
+    ) -> Result<Self, Vec<NetError>> {
+        let collision = |name: String| NetError::EmbedCollision {
+            prefix: prefix.into(),
+            name,
+        };
+        let mut errors = vec![];
+        if self.subnets.contains_key(prefix) {
+            errors.push(collision(prefix.into()));
+        }
+        for inner in subnet.subnets.keys() {
+            let name = format!("{}/{}", prefix, inner);
+            if self.subnets.contains_key(&name) {
+                errors.push(collision(name));
+            }
+        }
+        for t in subnet.transitions.keys().sorted() {
+            let name = format!("{}/{}", prefix, t);
+            if self.transitions.contains_key(&name) {
+                errors.push(collision(name));
+            }
+        }
+        for p in subnet.places.keys().sorted() {
+            let name = format!("{}/{}", prefix, p);
+            if !port_map.contains_key(p) && self.places.contains_key(&name) {
+                errors.push(collision(name));
+            }
+        }
+        if errors.is_empty() {
+            Ok(self.embed_unchecked(prefix, subnet, port_map))
+        } else {
+            Err(errors)
+        }
+    }
+    pub fn embed(self, prefix: &str, subnet: Net, port_map: HashMap<String, String>) -> Self {
+        match self.try_embed(prefix, subnet, port_map) {
+            Ok(net) => net,
+            Err(errors) => panic!(
+                "unable to embed '{}':\n{}",
+                prefix,
+                errors.iter().map(|e| e.to_string()).join("\n")
+            ),
+        }
+    }
+    fn embed_unchecked(
+        mut self,
+        prefix: &str,
+        subnet: Net,
+        port_map: HashMap<String, String>,
+    ) -> Self {
+        let place_name = |p: &String| -> String {
+            if let Some(port) = port_map.get(p) {
+                port.clone()
+            } else {
+                format!("{}/{}", prefix, p)
+            }
+        };
+        let transition_name = |t: &String| -> String { format!("{}/{}", prefix, t) };
+        let nested = subnet
+            .subnets
+            .values()
+            .flatten()
+            .cloned()
+            .collect::<HashSet<_>>();
+        let sub_transitions = subnet.transitions.keys().cloned().collect::<HashSet<_>>();
+        let mut members = HashSet::new();
+        for (t, maker) in subnet.transitions {
+            if !nested.contains(&t) {
+                members.insert(transition_name(&t));
+            }
+            self = self.add_transition(&transition_name(&t), maker);
+        }
+        for (t, d) in subnet.descriptions {
+            self.descriptions.insert(transition_name(&t), d);
+        }
+        for (t, priority) in subnet.priorities {
+            self = self.set_priority(&transition_name(&t), priority);
+        }
+        for (t, delay) in subnet.delays {
+            self = self.set_delay(&transition_name(&t), delay);
+        }
+        for (t, policy) in subnet.fault_policies {
+            let policy = match policy {
+                FaultPolicy::RouteTo(p) => FaultPolicy::RouteTo(place_name(&p)),
+                policy => policy,
+            };
+            self = self.set_fault_policy(&transition_name(&t), policy);
+        }
+        for (p, period) in subnet.timers {
+            self = self.add_timer(&place_name(&p), period);
+        }
+        for (p, (capacity, backpressure)) in subnet.capacities {
+            self = self.set_capacity(&place_name(&p), capacity, backpressure);
+        }
+        for (p, discipline) in subnet.disciplines {
+            self = self.set_queue_discipline(&place_name(&p), discipline);
+        }
+        for (p, token_qs) in subnet.places {
+            let name = place_name(&p);
+            if !nested.contains(&p) && !port_map.contains_key(&p) {
+                members.insert(name.clone());
+            }
+            self = self.add_place(&name);
+            for (ty, q) in token_qs {
+                self.places
+                    .get_mut(&name)
+                    .unwrap()
+                    .entry(ty)
+                    .or_default()
+                    .extend(q);
+            }
+        }
+        for p in subnet.external_places {
+            self = self.add_external_place(&place_name(&p));
+        }
+        for ((p, t), e) in subnet.pt_edges {
+            self = match subnet.pt_weights.get(&(p.clone(), t.clone())) {
+                Some(w) => {
+                    self.place_to_transition_weighted(&place_name(&p), &e, &transition_name(&t), *w)
+                }
+                None => self.place_to_transition(&place_name(&p), &e, &transition_name(&t)),
+            };
+        }
+        for ((t, p), e) in subnet.tp_edges {
+            self = match subnet.tp_weights.get(&(t.clone(), p.clone())) {
+                Some(w) => {
+                    self.transition_to_place_weighted(&transition_name(&t), &e, &place_name(&p), *w)
+                }
+                None => self.transition_to_place(&transition_name(&t), &e, &place_name(&p)),
+            };
+        }
+        for (p, t) in subnet.inhibitors {
+            self = self.inhibit(&place_name(&p), &transition_name(&t));
+        }
+        for (inner, inner_members) in subnet.subnets {
+            self.subnets.insert(
+                format!("{}/{}", prefix, inner),
+                inner_members
+                    .iter()
+                    .filter(|n| !port_map.contains_key(*n))
+                    .map(|n| {
+                        if sub_transitions.contains(n) {
+                            transition_name(n)
+                        } else {
+                            place_name(n)
+                        }
+                    })
+                    .collect(),
+            );
+        }
+        self.subnets.insert(prefix.into(), members);
+        self
+    }
+    fn subnet_parent(&self, prefix: &str) -> Option<String> {
+        let mut parent = prefix;
+        while let Some(i) = parent.rfind('/') {
+            parent = &parent[..i];
+            if self.subnets.contains_key(parent) {
+                return Some(parent.into());
+            }
+        }
+        None
+    }
+    fn subnet_as_dot(
+        &self,
+        prefix: &String,
+        node_dots: &mut HashMap<String, String>,
+        ids: &mut (String, usize),
+    ) -> String {
+        let mut inner = String::new();
+        for member in self.subnets[prefix].iter().sorted() {
+            if let Some(node_dot) = node_dots.remove(member) {
+                inner += &node_dot;
+            }
+        }
+        for child in self.subnets.keys() {
+            if self.subnet_parent(child).as_ref() == Some(prefix) {
+                inner += &self.subnet_as_dot(child, node_dots, ids);
+            }
+        }
+        if inner.is_empty() {
+            return inner;
+        }
+        ids.1 += 1;
+        format!(
+            "subgraph \"cluster_{}{}\" {{\nlabel=\"{}\";\n{}}}\n",
+            ids.0,
+            ids.1,
+            prefix.rsplit('/').next().unwrap(),
+            inner
+        )
+    }
     pub fn pseudo_hash(&self) -> u64 {
         let mut transitions = self.transitions.keys().collect::<Vec<_>>();
         transitions.sort();
@@ -177,13 +569,42 @@ impl Net {
             ts.sort();
             places_to_transitions.push((p, ts));
         }
-        let mut pt_edges: Vec<((String, String), String)> = vec![];
+        let mut pt_edges: Vec<((String, String), String, Option<usize>)> = vec![];
         for (pt, e) in self.pt_edges.iter().sorted_by_key(|x| x.0) {
-            pt_edges.push((pt.clone(), e.clone()));
+            pt_edges.push((pt.clone(), e.clone(), self.pt_weights.get(pt).cloned()));
         }
-        let mut tp_edges: Vec<((String, String), String)> = vec![];
+        let mut tp_edges: Vec<((String, String), String, Option<usize>)> = vec![];
         for (tp, e) in self.tp_edges.iter().sorted_by_key(|x| x.0) {
-            tp_edges.push((tp.clone(), e.clone()));
+            tp_edges.push((tp.clone(), e.clone(), self.tp_weights.get(tp).cloned()));
+        }
+        let inhibitors = self.inhibitors.iter().sorted().collect::<Vec<_>>();
+        let priorities = self.priorities.iter().sorted().collect::<Vec<_>>();
+        let timers = self.timers.iter().sorted().collect::<Vec<_>>();
+        let capacities = self.capacities.iter().sorted().collect::<Vec<_>>();
+        let disciplines = self
+            .disciplines
+            .iter()
+            .map(|(p, discipline)| (p, discipline.label()))
+            .sorted()
+            .collect::<Vec<_>>();
+        let delays = self.delays.iter().sorted().collect::<Vec<_>>();
+        let fault_policies = self
+            .fault_policies
+            .iter()
+            .map(|(t, policy)| (t, policy.label()))
+            .sorted()
+            .collect::<Vec<_>>();
+        let case_priorities = self
+            .descriptions
+            .iter()
+            .flat_map(|(t, d)| d.cases.iter().map(move |(c, case)| (t, c, case.priority)))
+            .sorted()
+            .collect::<Vec<_>>();
+        let mut subnets = vec![];
+        for (prefix, members) in self.subnets.iter() {
+            let mut members = members.iter().collect::<Vec<_>>();
+            members.sort();
+            subnets.push((prefix, members));
         }
         let mut s = DefaultHasher::new();
         let t = (
@@ -193,28 +614,104 @@ impl Net {
             places_to_transitions,
             pt_edges,
             tp_edges,
+            inhibitors,
+            priorities,
+            case_priorities,
+            (timers, delays, fault_policies, capacities, disciplines),
+            subnets,
         );
         t.hash(&mut s);
         s.finish()
     }
     pub fn as_dot(&self, multi_net: bool) -> (String, String) {
-        let mut dot = String::new();
+        self.as_dot_in(multi_net, "")
+    }
+    pub(crate) fn as_dot_in(&self, multi_net: bool, namespace: &str) -> (String, String) {
+        let mut ids = (namespace.to_string(), 0);
+        let mut node_dots = HashMap::new();
         for t in self.transitions.keys() {
-            dot += &format!("{}[label=\"{}\" shape=rectangle];\n", t, t);
+            let mut label = t.clone();
+            if let Some(priority) = self.priorities.get(t) {
+                label += &format!("\\npriority {}", priority);
+            }
+            if let Some(delay) = self.delays.get(t) {
+                label += &format!("\\ndelay {:?}", delay);
+            }
+            if let Some(policy) = self.fault_policies.get(t) {
+                label += &format!("\\non fault: {}", policy.label());
+            }
+            if let Some(d) = self.descriptions.get(t) {
+                for (c, case) in d.cases.iter().sorted_by_key(|x| x.0) {
+                    if case.priority != 0 {
+                        label += &format!("\\n{}: priority {}", c, case.priority);
+                    }
+                }
+            }
+            node_dots.insert(
+                t.clone(),
+                format!("\"{}\"[label=\"{}\" shape=rectangle];\n", t, label),
+            );
         }
         for p in self.places.keys() {
-            if multi_net {
-                if self.place_to_transitions[p].len() > 0 {
-                    dot += &format!("{}[label=\"{}\" shape=ellipse];\n", p, p);
+            if !multi_net
+                || !self.place_to_transitions[p].is_empty()
+                || self.inhibitors.iter().any(|(p2, _)| p2 == p)
+            {
+                let mut label = match self.timers.get(p) {
+                    Some(period) => format!("{}\\nevery {:?}", p, period),
+                    None => p.clone(),
+                };
+                if let Some(discipline) = self.disciplines.get(p) {
+                    label += &format!("\\n{}", discipline.label());
                 }
-            } else {
-                dot += &format!("{}[label=\"{}\" shape=ellipse];\n", p, p);
+                if let Some((capacity, backpressure)) = self.capacities.get(p) {
+                    label += &format!("\\ncapacity {} ({:?})", capacity, backpressure);
+                }
+                node_dots.insert(
+                    p.clone(),
+                    format!("\"{}\"[label=\"{}\" shape=ellipse];\n", p, label),
+                );
             }
         }
+        let mut dot = String::new();
+        for prefix in self.subnets.keys() {
+            if self.subnet_parent(prefix).is_none() {
+                dot += &self.subnet_as_dot(prefix, &mut node_dots, &mut ids);
+            }
+        }
+        for (_, node_dot) in node_dots.into_iter().sorted() {
+            dot += &node_dot;
+        }
         let mut dot_edges = String::new();
-        for connection_set in [&self.pt_edges, &self.tp_edges] {
+        for (connection_set, weights) in [
+            (&self.pt_edges, &self.pt_weights),
+            (&self.tp_edges, &self.tp_weights),
+        ] {
             for ((source, sink), name) in connection_set {
-                dot_edges += &format!("{} -> {}[label=\"{}\"];\n", source, sink, name);
+                let label = match weights.get(&(source.clone(), sink.clone())) {
+                    Some(w) => format!("{} ({})", name, w),
+                    None => name.clone(),
+                };
+                let is_error = self
+                    .descriptions
+                    .get(source)
+                    .is_some_and(|d| d.cases.values().any(|c| c.error.as_ref() == Some(name)));
+                let style = if is_error { " color=red" } else { "" };
+                dot_edges += &format!(
+                    "\"{}\" -> \"{}\"[label=\"{}\"{}];\n",
+                    source, sink, label, style
+                );
+            }
+        }
+        for (place, transition) in &self.inhibitors {
+            dot_edges += &format!("\"{}\" -> \"{}\"[arrowhead=odot];\n", place, transition);
+        }
+        for (place, transitions) in self.fault_routes() {
+            for transition in transitions {
+                dot_edges += &format!(
+                    "\"{}\" -> \"{}\"[label=\"fault\" style=dashed];\n",
+                    transition, place
+                );
             }
         }
         (dot, dot_edges)
@@ -233,7 +730,7 @@ impl Net {
             for (ty, token_q) in token_qs {
                 start_state.insert(
                     (place_name.clone(), *ty),
-                    (token_q.len(), token_q[0].type_name()),
+                    (token_q.len(), (*token_q[0]).type_name()),
                 );
             }
         }
@@ -272,15 +769,94 @@ use std::fmt::Debug;
 impl Debug for Net {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("Net")
-            .field(
-                "transitions",
-                &self.transitions.iter().map(|(k, _)| k).collect::<Vec<_>>(),
-            )
+            .field("transitions", &self.transitions.keys().collect::<Vec<_>>())
             .field("places", &self.places)
             .field("transition_to_places", &self.transition_to_places)
             .field("place_to_transitions", &self.place_to_transitions)
             .field("pt_edges", &self.pt_edges)
             .field("tp_edges", &self.tp_edges)
+            .field("pt_weights", &self.pt_weights)
+            .field("tp_weights", &self.tp_weights)
+            .field("inhibitors", &self.inhibitors)
+            .field("priorities", &self.priorities)
+            .field("timers", &self.timers)
+            .field("capacities", &self.capacities)
+            .field("disciplines", &self.disciplines)
+            .field("delays", &self.delays)
+            .field("fault_policies", &self.fault_policies)
+            .field("subnets", &self.subnets)
+            .field("external_places", &self.external_places)
             .finish()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::countdown::Countdown;
+
+    fn counter() -> Net {
+        Net::make()
+            .add_typed_transition("c", || Countdown {})
+            .place_to_transition("in", "n", "c")
+            .transition_to_place("c", "n", "in")
+            .transition_to_place("c", "done", "out")
+    }
+
+    #[test]
+    fn embed_prefixes_names_and_maps_ports() {
+        let net = Net::make()
+            .set_start_tokens("N", vec![Token::new(3u32)])
+            .embed(
+                "a",
+                counter(),
+                HashMap::from([("in".to_string(), "N".to_string())]),
+            );
+        assert!(net.transitions.contains_key("a/c"));
+        assert!(net.descriptions.contains_key("a/c"));
+        assert!(net.places.contains_key("a/out"));
+        assert!(!net.places.contains_key("a/in"));
+        assert_eq!(net.pt_edges[&("N".to_string(), "a/c".to_string())], "n");
+        assert_eq!(
+            net.subnets["a"],
+            HashSet::from(["a/c".to_string(), "a/out".to_string()])
+        );
+    }
+
+    #[test]
+    fn embed_rejects_collisions() {
+        let net = Net::make().embed("a", counter(), HashMap::new());
+        let errors = match net.try_embed("a", counter(), HashMap::new()) {
+            Ok(_) => panic!("embedding twice under one prefix succeeded"),
+            Err(errors) => errors,
+        };
+        let collision = |name: &str| NetError::EmbedCollision {
+            prefix: "a".into(),
+            name: name.into(),
+        };
+        assert!(errors.contains(&collision("a")));
+        assert!(errors.contains(&collision("a/c")));
+        assert!(errors.contains(&collision("a/in")));
+        assert!(errors.contains(&collision("a/out")));
+    }
+
+    #[test]
+    fn subnet_dot_ids_are_unique() {
+        let net =
+            Net::make()
+                .embed("a", counter(), HashMap::new())
+                .embed("b", counter(), HashMap::new());
+        let (nodes, _) = net.as_dot_in(false, "x_");
+        assert!(nodes.contains("subgraph \"cluster_x_1\""));
+        assert!(nodes.contains("subgraph \"cluster_x_2\""));
+    }
+
+    #[test]
+    fn start_state_names_token_types() {
+        let net = Net::make().set_start_tokens("N", vec![Token::new(3u32), Token::new(4u32)]);
+        assert_eq!(
+            net.start_state(),
+            HashMap::from([(("N".to_string(), TypeId::of::<u32>()), (2, "u32"))])
+        );
+    }
+}
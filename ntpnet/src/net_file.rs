@@ -0,0 +1,442 @@
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::path::Path;
+use std::time::Duration;
+
+use plotmux::plotmux::PlotMux;
+
+use crate::{
+    net::Net,
+    transition::{Describe, Description},
+    Backpressure, FaultPolicy, QueueDiscipline, Token, TransitionMaker,
+};
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(tag = "type", content = "value", rename_all = "snake_case")]
+pub enum StartToken {
+    Unit,
+    Bool(bool),
+    I32(i32),
+    I64(i64),
+    U32(u32),
+    U64(u64),
+    Usize(usize),
+    F32(f32),
+    F64(f64),
+    String(String),
+}
+impl From<StartToken> for Token {
+    fn from(t: StartToken) -> Self {
+        match t {
+            StartToken::Unit => Token::new(()),
+            StartToken::Bool(x) => Token::new(x),
+            StartToken::I32(x) => Token::new(x),
+            StartToken::I64(x) => Token::new(x),
+            StartToken::U32(x) => Token::new(x),
+            StartToken::U64(x) => Token::new(x),
+            StartToken::Usize(x) => Token::new(x),
+            StartToken::F32(x) => Token::new(x),
+            StartToken::F64(x) => Token::new(x),
+            StartToken::String(x) => Token::new(x),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum FaultEntry {
+    DropTokens,
+    RouteTo(String),
+    Shutdown,
+}
+impl From<FaultEntry> for FaultPolicy {
+    fn from(f: FaultEntry) -> Self {
+        match f {
+            FaultEntry::DropTokens => FaultPolicy::DropTokens,
+            FaultEntry::RouteTo(place) => FaultPolicy::RouteTo(place),
+            FaultEntry::Shutdown => FaultPolicy::Shutdown,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum BackpressureEntry {
+    DisableProducer,
+    Block,
+}
+impl From<BackpressureEntry> for Backpressure {
+    fn from(b: BackpressureEntry) -> Self {
+        match b {
+            BackpressureEntry::DisableProducer => Backpressure::DisableProducer,
+            BackpressureEntry::Block => Backpressure::Block,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum DisciplineEntry {
+    Fifo,
+    Lifo,
+    LatestOnly,
+}
+impl From<DisciplineEntry> for QueueDiscipline {
+    fn from(d: DisciplineEntry) -> Self {
+        match d {
+            DisciplineEntry::Fifo => QueueDiscipline::Fifo,
+            DisciplineEntry::Lifo => QueueDiscipline::Lifo,
+            DisciplineEntry::LatestOnly => QueueDiscipline::LatestOnly,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct TransitionEntry {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub ty: String,
+    #[serde(default)]
+    pub config: serde_json::Value,
+    #[serde(default)]
+    pub priority: Option<i32>,
+    #[serde(default)]
+    pub delay_ms: Option<u64>,
+    #[serde(default)]
+    pub on_fault: Option<FaultEntry>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct PlaceEntry {
+    pub name: String,
+    #[serde(default)]
+    pub start_tokens: Vec<StartToken>,
+    #[serde(default)]
+    pub timer_ms: Option<u64>,
+    #[serde(default)]
+    pub capacity: Option<usize>,
+    #[serde(default)]
+    pub backpressure: Option<BackpressureEntry>,
+    #[serde(default)]
+    pub discipline: Option<DisciplineEntry>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct PlaceToTransitionEntry {
+    pub place: String,
+    pub edge: String,
+    pub transition: String,
+    #[serde(default)]
+    pub weight: Option<usize>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct TransitionToPlaceEntry {
+    pub transition: String,
+    pub edge: String,
+    pub place: String,
+    #[serde(default)]
+    pub weight: Option<usize>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct InhibitorEntry {
+    pub place: String,
+    pub transition: String,
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct NetFile {
+    #[serde(default)]
+    pub transitions: Vec<TransitionEntry>,
+    #[serde(default)]
+    pub places: Vec<PlaceEntry>,
+    #[serde(default)]
+    pub place_to_transition: Vec<PlaceToTransitionEntry>,
+    #[serde(default)]
+    pub transition_to_place: Vec<TransitionToPlaceEntry>,
+    #[serde(default)]
+    pub inhibitors: Vec<InhibitorEntry>,
+    #[serde(default)]
+    pub work_clusters: Option<Vec<Vec<String>>>,
+}
+
+#[derive(Debug)]
+pub enum NetFileError {
+    Io(std::io::Error),
+    Parse(String),
+    UnknownTransitionType { name: String, ty: String },
+    Factory { name: String, message: String },
+    MissingBackpressure { place: String },
+}
+impl fmt::Display for NetFileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NetFileError::Io(e) => write!(f, "unable to read net file: {}", e),
+            NetFileError::Parse(e) => write!(f, "unable to parse net file: {}", e),
+            NetFileError::UnknownTransitionType { name, ty } => write!(
+                f,
+                "transition '{}' has type '{}' which is not in the registry",
+                name, ty
+            ),
+            NetFileError::Factory { name, message } => {
+                write!(f, "unable to make transition '{}': {}", name, message)
+            }
+            NetFileError::MissingBackpressure { place } => write!(
+                f,
+                "place '{}' has a capacity but no backpressure policy",
+                place
+            ),
+        }
+    }
+}
+impl std::error::Error for NetFileError {}
+
+pub struct TransitionConfig<'a> {
+    pub name: &'a str,
+    pub config: &'a serde_json::Value,
+}
+impl<'a> TransitionConfig<'a> {
+    pub fn get<T: DeserializeOwned>(&self) -> Result<T, String> {
+        serde_json::from_value(self.config.clone()).map_err(|e| e.to_string())
+    }
+}
+
+pub type TransitionFactory =
+    Box<dyn Fn(&TransitionConfig, &mut PlotMux) -> Result<TransitionMaker, String>>;
+type BuiltNet = (Net, Option<Vec<HashSet<String>>>);
+
+pub struct TransitionRegistry {
+    factories: HashMap<String, TransitionFactory>,
+    descriptions: HashMap<String, fn() -> Description>,
+}
+impl TransitionRegistry {
+    pub fn make() -> Self {
+        Self {
+            factories: HashMap::new(),
+            descriptions: HashMap::new(),
+        }
+    }
+    pub fn register<F>(mut self, ty: &str, factory: F) -> Self
+    where
+        F: Fn(&TransitionConfig, &mut PlotMux) -> Result<TransitionMaker, String> + 'static,
+    {
+        self.factories.insert(ty.into(), Box::new(factory));
+        self.descriptions.remove(ty);
+        self
+    }
+    pub fn register_typed<T, F>(mut self, ty: &str, factory: F) -> Self
+    where
+        T: Describe,
+        F: Fn(&TransitionConfig, &mut PlotMux) -> Result<TransitionMaker, String> + 'static,
+    {
+        self = self.register(ty, factory);
+        self.descriptions.insert(ty.into(), T::describe);
+        self
+    }
+}
+
+impl NetFile {
+    pub fn from_toml_str(s: &str) -> Result<Self, NetFileError> {
+        toml::from_str(s).map_err(|e| NetFileError::Parse(e.to_string()))
+    }
+    pub fn from_json_str(s: &str) -> Result<Self, NetFileError> {
+        serde_json::from_str(s).map_err(|e| NetFileError::Parse(e.to_string()))
+    }
+    pub fn load(path: &Path) -> Result<Self, NetFileError> {
+        let s = std::fs::read_to_string(path).map_err(NetFileError::Io)?;
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("json") => Self::from_json_str(&s),
+            _ => Self::from_toml_str(&s),
+        }
+    }
+    pub fn build(
+        self,
+        registry: &TransitionRegistry,
+        plotmux: &mut PlotMux,
+    ) -> Result<BuiltNet, NetFileError> {
+        let mut net = Net::make();
+        for t in &self.transitions {
+            let factory =
+                registry
+                    .factories
+                    .get(&t.ty)
+                    .ok_or(NetFileError::UnknownTransitionType {
+                        name: t.name.clone(),
+                        ty: t.ty.clone(),
+                    })?;
+            let config = TransitionConfig {
+                name: &t.name,
+                config: &t.config,
+            };
+            let maker = factory(&config, plotmux).map_err(|message| NetFileError::Factory {
+                name: t.name.clone(),
+                message,
+            })?;
+            net = net.add_transition(&t.name, maker);
+            if let Some(describe) = registry.descriptions.get(&t.ty) {
+                net.descriptions.insert(t.name.clone(), describe());
+            }
+            if let Some(priority) = t.priority {
+                net = net.set_priority(&t.name, priority);
+            }
+            if let Some(delay_ms) = t.delay_ms {
+                net = net.set_delay(&t.name, Duration::from_millis(delay_ms));
+            }
+            if let Some(on_fault) = t.on_fault.clone() {
+                net = net.set_fault_policy(&t.name, on_fault.into());
+            }
+        }
+        for p in self.places {
+            if let Some(timer_ms) = p.timer_ms {
+                net = net.add_timer(&p.name, Duration::from_millis(timer_ms));
+            }
+            if let Some(capacity) = p.capacity {
+                let backpressure = p.backpressure.ok_or(NetFileError::MissingBackpressure {
+                    place: p.name.clone(),
+                })?;
+                net = net.set_capacity(&p.name, capacity, backpressure.into());
+            }
+            if let Some(discipline) = p.discipline {
+                net = net.set_queue_discipline(&p.name, discipline.into());
+            }
+            net = net.set_start_tokens(
+                &p.name,
+                p.start_tokens.into_iter().map(|t| t.into()).collect(),
+            );
+        }
+        for e in &self.place_to_transition {
+            net = match e.weight {
+                Some(w) => net.place_to_transition_weighted(&e.place, &e.edge, &e.transition, w),
+                None => net.place_to_transition(&e.place, &e.edge, &e.transition),
+            };
+        }
+        for e in &self.transition_to_place {
+            net = match e.weight {
+                Some(w) => net.transition_to_place_weighted(&e.transition, &e.edge, &e.place, w),
+                None => net.transition_to_place(&e.transition, &e.edge, &e.place),
+            };
+        }
+        for i in &self.inhibitors {
+            net = net.inhibit(&i.place, &i.transition);
+        }
+        let work_clusters = self.work_clusters.map(|wcs| {
+            wcs.into_iter()
+                .map(|wc| wc.into_iter().collect::<HashSet<_>>())
+                .collect::<Vec<_>>()
+        });
+        Ok((net, work_clusters))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::countdown::Countdown;
+    use plotmux::plotmux::ClientMode;
+
+    const NET: &str = r#"
+        work_clusters = [["c"]]
+
+        [[transitions]]
+        name = "c"
+        type = "countdown"
+        priority = 2
+
+        [[places]]
+        name = "N"
+        start_tokens = [{ type = "u32", value = 3 }]
+
+        [[place_to_transition]]
+        place = "N"
+        edge = "n"
+        transition = "c"
+
+        [[transition_to_place]]
+        transition = "c"
+        edge = "n"
+        place = "N"
+
+        [[transition_to_place]]
+        transition = "c"
+        edge = "done"
+        place = "D"
+    "#;
+
+    fn registry() -> TransitionRegistry {
+        TransitionRegistry::make().register_typed::<Countdown, _>("countdown", |_, _| {
+            Ok(Box::new(|| Box::new(Countdown {})))
+        })
+    }
+
+    #[test]
+    fn toml_and_json_build_the_same_net() {
+        let json = r#"{
+            "transitions": [{ "name": "c", "type": "countdown", "priority": 2 }],
+            "places": [{ "name": "N", "start_tokens": [{ "type": "u32", "value": 3 }] }],
+            "place_to_transition": [{ "place": "N", "edge": "n", "transition": "c" }],
+            "transition_to_place": [
+                { "transition": "c", "edge": "n", "place": "N" },
+                { "transition": "c", "edge": "done", "place": "D" }
+            ],
+            "work_clusters": [["c"]]
+        }"#;
+        let mut plotmux = PlotMux::make(ClientMode::Local());
+        for file in [
+            NetFile::from_toml_str(NET).unwrap(),
+            NetFile::from_json_str(json).unwrap(),
+        ] {
+            let (mut net, work_clusters) = file.build(&registry(), &mut plotmux).unwrap();
+            assert_eq!(net.validate(), Ok(()));
+            assert_eq!(net.type_check(), Ok(()));
+            assert_eq!(net.priorities["c"], 2);
+            assert_eq!(
+                *net.places["N"][&std::any::TypeId::of::<u32>()][0]
+                    .downcast_ref::<u32>()
+                    .unwrap(),
+                3
+            );
+            assert!(net.descriptions.contains_key("c"));
+            assert_eq!(work_clusters, Some(vec![HashSet::from(["c".to_string()])]));
+        }
+    }
+
+    #[test]
+    fn unknown_transition_type_is_reported() {
+        let mut plotmux = PlotMux::make(ClientMode::Local());
+        let result = NetFile::from_toml_str(NET)
+            .unwrap()
+            .build(&TransitionRegistry::make(), &mut plotmux);
+        match result {
+            Err(NetFileError::UnknownTransitionType { name, ty }) => {
+                assert_eq!((name.as_str(), ty.as_str()), ("c", "countdown"))
+            }
+            _ => panic!("expected an unknown transition type"),
+        }
+    }
+
+    #[test]
+    fn register_replaces_a_typed_description() {
+        let registry =
+            registry().register("countdown", |_, _| Ok(Box::new(|| Box::new(Countdown {}))));
+        let mut plotmux = PlotMux::make(ClientMode::Local());
+        let (net, _) = NetFile::from_toml_str(NET)
+            .unwrap()
+            .build(&registry, &mut plotmux)
+            .unwrap();
+        assert!(!net.descriptions.contains_key("c"));
+    }
+
+    #[test]
+    fn capacity_requires_backpressure() {
+        let mut file = NetFile::from_toml_str(NET).unwrap();
+        file.places[0].capacity = Some(1);
+        let mut plotmux = PlotMux::make(ClientMode::Local());
+        match file.build(&registry(), &mut plotmux) {
+            Err(NetFileError::MissingBackpressure { place }) => assert_eq!(place, "N"),
+            _ => panic!("expected a missing backpressure policy"),
+        }
+    }
+}
@@ -0,0 +1,271 @@
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use plotmux::plotsink::PlotSink;
+
+use crate::PlotOptions;
+
+pub trait ReactorObserver: Send + Sync {
+    fn on_start(&self, _work_cluster: usize, _transitions: &[&str]) {}
+    fn on_fire_start(
+        &self,
+        _work_cluster: usize,
+        _transition: &str,
+        _case: &str,
+        _condition: usize,
+    ) {
+    }
+    fn on_fire_end(
+        &self,
+        _work_cluster: usize,
+        _transition: &str,
+        _case: &str,
+        _elapsed: Duration,
+    ) {
+    }
+    fn on_token_push(&self, _work_cluster: usize, _place: &str, _ty: &str, _count: usize) {}
+    fn on_token_pop(&self, _work_cluster: usize, _place: &str, _ty: &str, _count: usize) {}
+    fn on_block(&self, _work_cluster: usize) {}
+    fn on_unblock(&self, _work_cluster: usize, _blocked: Duration) {}
+    fn on_marking(&self, _place: &str, _ty: &str, _count: i64) {}
+    fn on_memory(&self, _usage: &[(&str, f64)]) {}
+    fn on_deadlock(&self, _marking: &[(&str, &str, i64)]) {}
+    fn on_exit(&self, _work_cluster: usize) {}
+}
+
+#[derive(Clone, Default)]
+pub(crate) struct Observers(Vec<Arc<dyn ReactorObserver>>);
+impl Observers {
+    pub fn make(observers: Vec<Arc<dyn ReactorObserver>>) -> Self {
+        Self(observers)
+    }
+    pub fn notify(&self, f: impl Fn(&dyn ReactorObserver)) {
+        for observer in &self.0 {
+            f(&**observer);
+        }
+    }
+}
+impl fmt::Debug for Observers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Observers({})", self.0.len())
+    }
+}
+
+pub(crate) struct PlotObserver {
+    options: PlotOptions,
+    start: Instant,
+    work_clusters: Vec<(Arc<Mutex<PlotSink>>, Mutex<Instant>)>,
+    pseudo_state: Arc<Mutex<PlotSink>>,
+    memory: Mutex<PlotSink>,
+}
+impl PlotObserver {
+    pub fn make(
+        options: PlotOptions,
+        work_clusters: Vec<Arc<Mutex<PlotSink>>>,
+        pseudo_state: Arc<Mutex<PlotSink>>,
+        memory: PlotSink,
+    ) -> Self {
+        let start = Instant::now();
+        Self {
+            options,
+            start,
+            work_clusters: work_clusters
+                .into_iter()
+                .map(|sink| (sink, Mutex::new(start)))
+                .collect(),
+            pseudo_state,
+            memory: Mutex::new(memory),
+        }
+    }
+    fn now(&self) -> f64 {
+        (Instant::now() - self.start).as_secs_f64()
+    }
+    fn plot(&self, work_cluster: usize, plot: &str, series: &str, y: f64) {
+        let x = self.now();
+        self.work_clusters[work_cluster]
+            .0
+            .lock()
+            .unwrap()
+            .plot_series_2d(plot, series, x, y);
+    }
+    fn nonblocking(&self, work_cluster: usize) -> Duration {
+        let now = Instant::now();
+        let mut last = self.work_clusters[work_cluster].1.lock().unwrap();
+        let nonblocking = now - *last;
+        *last = now;
+        nonblocking
+    }
+}
+impl ReactorObserver for PlotObserver {
+    fn on_start(&self, work_cluster: usize, transitions: &[&str]) {
+        self.nonblocking(work_cluster);
+        let mut sink = self.work_clusters[work_cluster].0.lock().unwrap();
+        if self.options.reactor_timing {
+            sink.plot_series_2d("reactor timing", "blocking", 0.0, 0.0);
+            sink.plot_series_2d("reactor timing", "nonblocking", 0.0, 0.0);
+        }
+        if self.options.transition_timing {
+            for t_name in transitions {
+                sink.plot_series_2d("transition timing", t_name, 0.0, 0.0);
+            }
+        }
+    }
+    fn on_fire_start(
+        &self,
+        work_cluster: usize,
+        _transition: &str,
+        _case: &str,
+        _condition: usize,
+    ) {
+        let nonblocking = self.nonblocking(work_cluster);
+        if self.options.reactor_timing {
+            self.plot(
+                work_cluster,
+                "reactor timing",
+                "nonblocking",
+                nonblocking.as_secs_f64(),
+            );
+        }
+    }
+    fn on_fire_end(&self, work_cluster: usize, transition: &str, _case: &str, elapsed: Duration) {
+        self.nonblocking(work_cluster);
+        if self.options.transition_timing {
+            self.plot(
+                work_cluster,
+                "transition timing",
+                transition,
+                elapsed.as_secs_f64(),
+            );
+        }
+    }
+    fn on_token_push(&self, work_cluster: usize, place: &str, ty: &str, count: usize) {
+        if self.options.local_state {
+            self.plot(
+                work_cluster,
+                "local state",
+                &format!("{}/{}", place, ty),
+                count as f64,
+            );
+        }
+    }
+    fn on_token_pop(&self, work_cluster: usize, place: &str, ty: &str, count: usize) {
+        self.on_token_push(work_cluster, place, ty, count);
+    }
+    fn on_unblock(&self, work_cluster: usize, blocked: Duration) {
+        self.nonblocking(work_cluster);
+        if self.options.reactor_timing {
+            self.plot(
+                work_cluster,
+                "reactor timing",
+                "blocking",
+                blocked.as_secs_f64(),
+            );
+        }
+    }
+    fn on_marking(&self, place: &str, ty: &str, count: i64) {
+        if self.options.pseudo_state {
+            let x = self.now();
+            self.pseudo_state.lock().unwrap().plot_series_2d(
+                "pseudo-state",
+                &format!("{}/{}", place, ty),
+                x,
+                count as f64,
+            );
+        }
+    }
+    fn on_memory(&self, usage: &[(&str, f64)]) {
+        if self.options.memory_profile.is_some() {
+            let x = self.now();
+            let mut sink = self.memory.lock().unwrap();
+            for (series, value) in usage {
+                sink.plot_series_2d("memory usage", series, x, *value);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::countdown::Countdown;
+    use crate::testing::work_cluster;
+    use crate::{Net, Token};
+
+    #[derive(Default)]
+    struct Log(Mutex<Vec<String>>);
+    impl Log {
+        fn push(&self, event: String) {
+            self.0.lock().unwrap().push(event);
+        }
+    }
+    impl ReactorObserver for Log {
+        fn on_start(&self, work_cluster: usize, transitions: &[&str]) {
+            self.push(format!("start {} {:?}", work_cluster, transitions));
+        }
+        fn on_fire_start(&self, work_cluster: usize, transition: &str, case: &str, _: usize) {
+            self.push(format!("fire {} {}:{}", work_cluster, transition, case));
+        }
+        fn on_fire_end(&self, work_cluster: usize, transition: &str, case: &str, _: Duration) {
+            self.push(format!("fired {} {}:{}", work_cluster, transition, case));
+        }
+        fn on_token_push(&self, _: usize, place: &str, ty: &str, count: usize) {
+            self.push(format!("push {}/{} {}", place, ty, count));
+        }
+        fn on_block(&self, work_cluster: usize) {
+            self.push(format!("block {}", work_cluster));
+        }
+        fn on_exit(&self, work_cluster: usize) {
+            self.push(format!("exit {}", work_cluster));
+        }
+    }
+
+    #[test]
+    fn notify_reaches_every_observer() {
+        let logs = [Arc::new(Log::default()), Arc::new(Log::default())];
+        let observers = Observers::make(
+            logs.iter()
+                .map(|l| l.clone() as Arc<dyn ReactorObserver>)
+                .collect(),
+        );
+        observers.notify(|o| o.on_block(1));
+        for log in &logs {
+            assert_eq!(*log.0.lock().unwrap(), vec!["block 1"]);
+        }
+        assert_eq!(format!("{:?}", observers), "Observers(2)");
+    }
+
+    #[test]
+    fn work_clusters_notify_observers() {
+        let log = Arc::new(Log::default());
+        let (mut wc, _exit_tx) = work_cluster(
+            Net::make()
+                .set_start_tokens("N", vec![Token::new(1u32)])
+                .add_typed_transition("c", || Countdown {})
+                .place_to_transition("N", "n", "c")
+                .transition_to_place("c", "n", "N")
+                .transition_to_place("c", "done", "D"),
+        );
+        wc.observe(2, Observers::make(vec![log.clone()]));
+        wc.begin();
+        wc.fire();
+        wc.take_places();
+        let events = log.0.lock().unwrap();
+        assert_eq!(
+            events
+                .iter()
+                .filter(|e| !e.starts_with("push"))
+                .collect::<Vec<_>>(),
+            vec![
+                "start 2 [\"c\"]",
+                "fire 2 c:count",
+                "fired 2 c:count",
+                "fire 2 c:count",
+                "fired 2 c:count",
+                "block 2",
+                "exit 2",
+            ]
+        );
+        assert!(events.iter().any(|e| e == "push D/u32 1"));
+    }
+}
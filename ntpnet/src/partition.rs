@@ -0,0 +1,152 @@
+use itertools::Itertools;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use crate::net::Net;
+
+fn find(parents: &mut HashMap<String, String>, t: &String) -> String {
+    let parent = parents[t].clone();
+    if &parent == t {
+        parent
+    } else {
+        let root = find(parents, &parent);
+        parents.insert(t.clone(), root.clone());
+        root
+    }
+}
+
+fn union(parents: &mut HashMap<String, String>, a: &String, b: &String) {
+    let a = find(parents, a);
+    let b = find(parents, b);
+    if a < b {
+        parents.insert(b, a);
+    } else if b < a {
+        parents.insert(a, b);
+    }
+}
+
+pub fn maximal_work_clusters(net: &Net, pinned: &[HashSet<String>]) -> Vec<HashSet<String>> {
+    let mut parents = net
+        .transitions
+        .keys()
+        .map(|t| (t.clone(), t.clone()))
+        .collect::<HashMap<_, _>>();
+    let readers = net.place_readers();
+    let groups = readers
+        .values()
+        .chain(pinned.iter())
+        .map(|ts| {
+            ts.iter()
+                .filter(|t| parents.contains_key(*t))
+                .sorted()
+                .cloned()
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+    for ts in groups {
+        for t in ts.iter().skip(1) {
+            union(&mut parents, &ts[0], t);
+        }
+    }
+    let mut clusters: BTreeMap<String, HashSet<String>> = BTreeMap::new();
+    for t in net.transitions.keys().sorted() {
+        let root = find(&mut parents, t);
+        clusters.entry(root).or_default().insert(t.clone());
+    }
+    clusters.into_values().collect()
+}
+
+pub fn merge_work_clusters(net: &Net, work_clusters: &[HashSet<String>]) -> Vec<HashSet<String>> {
+    let readers = net.place_readers();
+    let mut merged = work_clusters.to_vec();
+    loop {
+        let conflict = readers.values().find_map(|ts| {
+            let clusters = merged
+                .iter()
+                .positions(|cluster| ts.iter().any(|t| cluster.contains(t)))
+                .collect::<Vec<_>>();
+            if clusters.len() > 1 {
+                Some(clusters)
+            } else {
+                None
+            }
+        });
+        if let Some(clusters) = conflict {
+            for i in clusters[1..].iter().rev() {
+                let cluster = merged.remove(*i);
+                merged[clusters[0]].extend(cluster);
+            }
+        } else {
+            break;
+        }
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::countdown::Countdown;
+    use crate::{MultiReactor, NetError, Token, TransitionMaker};
+    use plotmux::plotmux::{ClientMode, PlotMux};
+
+    fn countdown() -> TransitionMaker {
+        Box::new(|| Box::new(Countdown {}))
+    }
+
+    fn net() -> Net {
+        Net::make()
+            .set_start_tokens("A", vec![Token::new(1u32)])
+            .set_start_tokens("B", vec![Token::new(1u32)])
+            .add_transition("a1", countdown())
+            .add_transition("a2", countdown())
+            .add_transition("b", countdown())
+            .add_transition("c", countdown())
+            .place_to_transition("A", "n", "a1")
+            .place_to_transition("A", "n", "a2")
+            .place_to_transition("B", "n", "b")
+            .transition_to_place("b", "n", "C")
+            .place_to_transition("C", "n", "c")
+    }
+
+    fn set(ts: &[&str]) -> HashSet<String> {
+        ts.iter().map(|t| t.to_string()).collect()
+    }
+
+    #[test]
+    fn transitions_sharing_an_input_place_share_a_cluster() {
+        assert_eq!(
+            maximal_work_clusters(&net(), &[]),
+            vec![set(&["a1", "a2"]), set(&["b"]), set(&["c"])]
+        );
+    }
+
+    #[test]
+    fn pinned_transitions_share_a_cluster() {
+        assert_eq!(
+            maximal_work_clusters(&net(), &[set(&["b", "c"])]),
+            vec![set(&["a1", "a2"]), set(&["b", "c"])]
+        );
+    }
+
+    #[test]
+    fn merge_joins_clusters_that_split_a_place() {
+        let merged = merge_work_clusters(&net(), &[set(&["a1", "b"]), set(&["a2"]), set(&["c"])]);
+        assert_eq!(merged, vec![set(&["a1", "a2", "b"]), set(&["c"])]);
+    }
+
+    #[test]
+    fn try_make_reports_split_input_place() {
+        let mut plotmux = PlotMux::make(ClientMode::Local());
+        let clusters = vec![set(&["a1", "b"]), set(&["a2"]), set(&["c"])];
+        let errors = match MultiReactor::try_make(net(), clusters, &mut plotmux) {
+            Ok(_) => panic!("split input place accepted"),
+            Err(errors) => errors,
+        };
+        assert!(
+            errors.contains(&NetError::PlaceConsumedByMultipleWorkClusters {
+                place: "A".into(),
+                clusters: vec![0, 1],
+            })
+        );
+    }
+}
@@ -1,99 +1,140 @@
-use crossbeam_channel::{Receiver, Sender};
+use crossbeam_channel::{select, Receiver, Sender};
 use defer::defer;
 use std::any::TypeId;
-use std::collections::{BTreeSet, HashMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::sync::{Arc, Mutex};
 use std::thread;
-use std::time::Instant;
 
 use crate::{
+    observer::Observers,
     state::{StateBlockable, StateDelta},
     PlotOptions,
 };
 use plotmux::plotsink::PlotSink;
 
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct NonblockingState {
+    pub weights: BTreeMap<(String, TypeId), usize>,
+    pub inhibitors: BTreeSet<String>,
+    pub bounds: BTreeMap<String, (usize, usize)>,
+    pub guard: Option<(String, String, usize)>,
+}
+
+#[allow(clippy::too_many_arguments)]
 pub fn pseudo_state_monitor(
     start_state: HashMap<(String, TypeId), (i64, &'static str)>,
-    nonblocking_states: HashSet<BTreeSet<(String, TypeId)>>,
+    nonblocking_states: HashSet<NonblockingState>,
+    external_places: HashMap<String, Arc<()>>,
     state_delta_monitor: Receiver<StateDelta>,
+    done: Receiver<()>,
     exit_txs: Vec<Sender<StateBlockable>>,
-    mut plot_sink: PlotSink,
+    observers: Observers,
+    plot_sink: Arc<Mutex<PlotSink>>,
     plot_options: PlotOptions,
 ) -> impl Drop {
     let t = thread::Builder::new()
         .name("pseudo_state_monitor".into())
         .spawn(move || {
             for ((place, _ty), (len, ty_name)) in &start_state {
-                if plot_options.pseudo_state {
-                    plot_sink.plot_series_2d(
-                        "pseudo-state",
-                        &format!("{}/{}", place, ty_name),
-                        0.0,
-                        *len as f64,
-                    );
-                }
+                observers.notify(|o| o.on_marking(place, ty_name, *len));
             }
             let mut state = start_state;
-            let mut state_binary: BTreeSet<(String, TypeId)> = state.keys().cloned().collect();
-            let start = Instant::now();
+            let mut rejected_guards: HashMap<_, BTreeMap<(String, TypeId), usize>> = HashMap::new();
+            let mut running = true;
+            let mut in_flight = 0;
             loop {
                 let mut deadlock = true;
                 for nonblocking_state in &nonblocking_states {
-                    if nonblocking_state.is_subset(&state_binary) {
+                    let count = |p_ty: &(String, TypeId)| state.get(p_ty).map_or(0, |s| s.0);
+                    let place_count = |place: &String| {
+                        state
+                            .iter()
+                            .filter(|((p, _), _)| p == place)
+                            .map(|(_, (n, _))| n)
+                            .sum::<i64>()
+                    };
+                    let injected = |p: &String| {
+                        external_places
+                            .get(p)
+                            .is_some_and(|alive| Arc::strong_count(alive) > 1)
+                    };
+                    let rejected = nonblocking_state.guard.as_ref().is_some_and(|guard| {
+                        rejected_guards.get(guard).is_some_and(|snapshot| {
+                            snapshot.iter().all(|(p_ty, n)| count(p_ty) == *n as i64)
+                        })
+                    });
+                    if nonblocking_state
+                        .weights
+                        .iter()
+                        .all(|(p_ty, w)| count(p_ty) >= *w as i64 || injected(&p_ty.0))
+                        && !state.iter().any(|((p, _), (n, _))| {
+                            *n > 0 && nonblocking_state.inhibitors.contains(p)
+                        })
+                        && nonblocking_state.bounds.iter().all(|(p, (w, capacity))| {
+                            place_count(p) + *w as i64 <= *capacity as i64
+                        })
+                        && !rejected
+                    {
                         deadlock = false;
                         break;
                     }
                 }
-                if deadlock {
+                if deadlock && in_flight == 0 {
+                    let marking = state
+                        .iter()
+                        .filter(|(_, (n, _))| *n != 0)
+                        .map(|((place, _), (n, ty_name))| (place.as_str(), *ty_name, *n))
+                        .collect::<Vec<_>>();
+                    observers.notify(|o| o.on_deadlock(&marking));
                     break;
                 }
-                if let Ok(state_delta) = state_delta_monitor.recv() {
-                    let now = (Instant::now() - start).as_secs_f64();
-                    let (sub, add) = state_delta.take();
-                    for s in sub {
-                        *&mut state.get_mut(&s).unwrap().0 -= 1;
-                        if !add.contains_key(&s) && plot_options.pseudo_state {
-                            plot_sink.plot_series_2d(
-                                "pseudo-state",
-                                &format!("{}/{}", &s.0, &state[&s].1),
-                                now,
-                                state[&s].0 as f64,
-                            );
-                        }
-                        if state[&s].0 == 0 {
-                            state_binary.remove(&s);
+                let state_delta = select! {
+                    recv(state_delta_monitor) -> state_delta => state_delta,
+                    recv(done) -> _ => Err(crossbeam_channel::RecvError),
+                };
+                if let Ok(state_delta) = state_delta {
+                    let (sub, add, rejected, fault, delta_in_flight) = state_delta.take();
+                    in_flight += delta_in_flight;
+                    rejected_guards.extend(rejected);
+                    if let Some(fault) = fault {
+                        plot_sink.lock().unwrap().println(&format!(
+                            "shutting down after fault: {}\nstate: {:?}",
+                            fault,
+                            state
+                                .iter()
+                                .filter(|((_, _), (s, _))| *s != 0)
+                                .collect::<Vec<_>>()
+                        ));
+                        break;
+                    }
+                    for (s, n) in sub {
+                        state.get_mut(&s).unwrap().0 -= n as i64;
+                        if !add.contains_key(&s) {
+                            observers.notify(|o| o.on_marking(&s.0, state[&s].1, state[&s].0));
                         }
                     }
-                    for ((place, ty), ty_name) in add {
+                    for ((place, ty), (ty_name, n)) in add {
                         let key = (place, ty);
-                        if !state.contains_key(&key) {
-                            state.insert(key.clone(), (1, ty_name));
-                            state_binary.insert(key.clone());
-                        } else {
-                            *&mut state.get_mut(&key).unwrap().0 += 1;
-                        }
-                        if plot_options.pseudo_state {
-                            plot_sink.plot_series_2d(
-                                "pseudo-state",
-                                &format!("{}/{}", &key.0, &ty_name),
-                                now,
-                                state[&key].0 as f64,
-                            );
-                        }
-                        if state[&key].0 >= 1 {
-                            state_binary.insert(key);
-                        }
+                        state.entry(key.clone()).or_insert((0, ty_name)).0 += n as i64;
+                        observers.notify(|o| o.on_marking(&key.0, ty_name, state[&key].0));
                     }
                 } else {
+                    running = false;
                     break;
                 }
             }
-            for (i, tx) in exit_txs.into_iter().enumerate() {
-                if let Err(_) = tx.send(StateBlockable::Terminate(())) {
-                    plot_sink.println(&format!("failed to terminate work-cluster-{}", i));
+            if running {
+                for (i, tx) in exit_txs.into_iter().enumerate() {
+                    if tx.send(StateBlockable::Terminate(())).is_err() {
+                        plot_sink
+                            .lock()
+                            .unwrap()
+                            .println(&format!("failed to terminate work-cluster-{}", i));
+                    }
                 }
             }
             if plot_options.pseudo_state {
-                plot_sink.println(&format!(
+                plot_sink.lock().unwrap().println(&format!(
                     "exiting with state: {:?}",
                     state
                         .iter()
@@ -105,3 +146,50 @@ pub fn pseudo_state_monitor(
         .expect("unable to spawn monitor thread");
     defer(|| t.join().expect("unable to join monitor thread"))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crossbeam_channel::unbounded;
+    use plotmux::plotmux::{ClientMode, PlotMux};
+    use std::mem;
+    use std::time::Duration;
+
+    #[test]
+    fn full_output_places_disable_producers() {
+        let plot_sink = Arc::new(Mutex::new(
+            PlotMux::make(ClientMode::Local()).add_plot_sink("test"),
+        ));
+        mem::forget(plot_sink.clone());
+        let u32_ty = TypeId::of::<u32>();
+        let start_state = HashMap::from([
+            (("N".into(), u32_ty), (1, "u32")),
+            (("F".into(), u32_ty), (1, "u32")),
+        ]);
+        let producer = NonblockingState {
+            weights: BTreeMap::from([(("N".into(), u32_ty), 1)]),
+            inhibitors: BTreeSet::new(),
+            bounds: BTreeMap::from([("F".into(), (1, 1))]),
+            guard: None,
+        };
+        let (exit_tx, exit_rx) = unbounded();
+        let (_state_delta_tx, state_delta_rx) = unbounded();
+        let (_done_tx, done_rx) = unbounded();
+        let monitor = pseudo_state_monitor(
+            start_state,
+            HashSet::from([producer]),
+            HashMap::new(),
+            state_delta_rx,
+            done_rx,
+            vec![exit_tx],
+            Observers::default(),
+            plot_sink,
+            PlotOptions::default(),
+        );
+        assert!(matches!(
+            exit_rx.recv_timeout(Duration::from_secs(5)),
+            Ok(StateBlockable::Terminate(()))
+        ));
+        drop(monitor);
+    }
+}
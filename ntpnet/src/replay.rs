@@ -0,0 +1,148 @@
+use crossbeam_channel::{Receiver, Sender};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::state::StateBlockable;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Firing {
+    pub time: f64,
+    pub work_cluster: usize,
+    pub transition: String,
+    pub case: String,
+    pub condition: usize,
+    pub product: Option<usize>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Recording {
+    pub firings: Vec<Firing>,
+}
+impl Recording {
+    pub fn read(path: &Path) -> io::Result<Self> {
+        Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+    }
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, serde_json::to_string_pretty(self)?)
+    }
+}
+
+pub struct Recorder {
+    rx: Receiver<Firing>,
+}
+impl Recorder {
+    pub(crate) fn make(rx: Receiver<Firing>) -> Self {
+        Self { rx }
+    }
+    pub fn recording(&self) -> Recording {
+        Recording {
+            firings: self.rx.try_iter().collect(),
+        }
+    }
+}
+
+pub(crate) enum Turn {
+    Free,
+    Wait,
+    Fire(usize, Firing),
+}
+
+#[derive(Debug)]
+pub(crate) struct Replay {
+    firings: Vec<Firing>,
+    next: AtomicUsize,
+    wakers: Vec<Sender<StateBlockable>>,
+}
+impl Replay {
+    pub fn make(recording: Recording, wakers: Vec<Sender<StateBlockable>>) -> Self {
+        Self {
+            firings: recording.firings,
+            next: AtomicUsize::new(0),
+            wakers,
+        }
+    }
+    pub fn turn(&self, work_cluster: usize) -> Turn {
+        let next = self.next.load(Ordering::SeqCst);
+        match self.firings.get(next) {
+            Some(f) if f.work_cluster == work_cluster => Turn::Fire(next, f.clone()),
+            Some(_) => Turn::Wait,
+            None => Turn::Free,
+        }
+    }
+    pub fn advance(&self) {
+        let next = self.next.fetch_add(1, Ordering::SeqCst) + 1;
+        match self.firings.get(next) {
+            Some(f) => {
+                let _ = self.wakers[f.work_cluster].send(StateBlockable::Released(()));
+            }
+            None => {
+                for waker in &self.wakers {
+                    let _ = waker.send(StateBlockable::Released(()));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crossbeam_channel::unbounded;
+    use tempfile::NamedTempFile;
+
+    fn firing(work_cluster: usize, transition: &str) -> Firing {
+        Firing {
+            time: 0.0,
+            work_cluster,
+            transition: transition.into(),
+            case: "count".into(),
+            condition: 0,
+            product: None,
+        }
+    }
+
+    fn recording() -> Recording {
+        Recording {
+            firings: vec![firing(0, "a"), firing(1, "b"), firing(1, "c")],
+        }
+    }
+
+    fn released(rx: &Receiver<StateBlockable>) -> usize {
+        rx.try_iter()
+            .filter(|s| matches!(s, StateBlockable::Released(_)))
+            .count()
+    }
+
+    #[test]
+    fn turn_follows_the_recording() {
+        let (tx0, rx0) = unbounded();
+        let (tx1, rx1) = unbounded();
+        let replay = Replay::make(recording(), vec![tx0, tx1]);
+        assert!(matches!(replay.turn(0), Turn::Fire(0, f) if f.transition == "a"));
+        assert!(matches!(replay.turn(1), Turn::Wait));
+        replay.advance();
+        assert_eq!((released(&rx0), released(&rx1)), (0, 1));
+        assert!(matches!(replay.turn(0), Turn::Wait));
+        assert!(matches!(replay.turn(1), Turn::Fire(1, f) if f.transition == "b"));
+        replay.advance();
+        assert_eq!((released(&rx0), released(&rx1)), (0, 1));
+        assert!(matches!(replay.turn(1), Turn::Fire(2, f) if f.transition == "c"));
+        replay.advance();
+        assert_eq!((released(&rx0), released(&rx1)), (1, 1));
+        assert!(matches!(replay.turn(0), Turn::Free));
+        assert!(matches!(replay.turn(1), Turn::Free));
+    }
+
+    #[test]
+    fn recording_round_trips_through_a_file() {
+        let file = NamedTempFile::new().unwrap();
+        recording().write(file.path()).unwrap();
+        assert_eq!(
+            Recording::read(file.path()).unwrap().firings,
+            recording().firings
+        );
+    }
+}
@@ -0,0 +1,165 @@
+use rand::{distributions::WeightedIndex, prelude::Distribution, rngs::StdRng, Rng, SeedableRng};
+use std::collections::HashMap;
+use std::fmt::{Debug, Result};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Candidate<'a> {
+    pub transition: &'a str,
+    pub case: &'a str,
+    pub condition: usize,
+}
+
+pub trait SchedulingPolicy: Send {
+    /// Picks the candidate to fire and returns its index. `candidates` is never
+    /// empty; an index past the end is clamped to the last candidate.
+    fn select(&mut self, candidates: &[Candidate]) -> usize;
+}
+impl Debug for dyn SchedulingPolicy {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> Result {
+        write!(f, "SchedulingPolicy")
+    }
+}
+
+pub struct FirstMatch {}
+impl FirstMatch {
+    pub fn make() -> Self {
+        Self {}
+    }
+}
+impl SchedulingPolicy for FirstMatch {
+    fn select(&mut self, _candidates: &[Candidate]) -> usize {
+        0
+    }
+}
+
+pub struct RoundRobin {
+    last: Option<String>,
+}
+impl RoundRobin {
+    pub fn make() -> Self {
+        Self { last: None }
+    }
+}
+impl SchedulingPolicy for RoundRobin {
+    fn select(&mut self, candidates: &[Candidate]) -> usize {
+        let choice = match &self.last {
+            Some(last) => candidates
+                .iter()
+                .position(|c| c.transition > last.as_str())
+                .unwrap_or(0),
+            None => 0,
+        };
+        self.last = Some(candidates[choice].transition.into());
+        choice
+    }
+}
+
+pub struct SeededRandom {
+    rng: StdRng,
+}
+impl SeededRandom {
+    pub fn make(seed: u64) -> Self {
+        Self {
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+}
+impl SchedulingPolicy for SeededRandom {
+    fn select(&mut self, candidates: &[Candidate]) -> usize {
+        self.rng.gen_range(0..candidates.len())
+    }
+}
+
+pub struct Weighted {
+    weights: HashMap<String, f64>,
+    rng: StdRng,
+}
+impl Weighted {
+    pub fn make(weights: HashMap<String, f64>, seed: u64) -> Self {
+        Self {
+            weights,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+}
+impl SchedulingPolicy for Weighted {
+    fn select(&mut self, candidates: &[Candidate]) -> usize {
+        let weights = candidates
+            .iter()
+            .map(|c| *self.weights.get(c.transition).unwrap_or(&1.0))
+            .collect::<Vec<_>>();
+        match WeightedIndex::new(&weights) {
+            Ok(dist) => dist.sample(&mut self.rng),
+            Err(_) => 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{countdown::Countdown, tokens, work_cluster};
+    use crate::{Net, Token};
+
+    fn candidates<'a>(transitions: &[&'a str]) -> Vec<Candidate<'a>> {
+        transitions
+            .iter()
+            .map(|t| Candidate {
+                transition: t,
+                case: "count",
+                condition: 0,
+            })
+            .collect()
+    }
+
+    fn picks(policy: &mut dyn SchedulingPolicy, n: usize) -> Vec<usize> {
+        let candidates = candidates(&["a", "b", "c"]);
+        (0..n).map(|_| policy.select(&candidates)).collect()
+    }
+
+    #[test]
+    fn first_match_picks_the_first_candidate() {
+        assert_eq!(picks(&mut FirstMatch::make(), 3), vec![0, 0, 0]);
+    }
+
+    #[test]
+    fn round_robin_cycles_through_transitions() {
+        assert_eq!(picks(&mut RoundRobin::make(), 4), vec![0, 1, 2, 0]);
+    }
+
+    #[test]
+    fn seeded_random_is_reproducible() {
+        let a = picks(&mut SeededRandom::make(7), 32);
+        assert_eq!(a, picks(&mut SeededRandom::make(7), 32));
+        assert!(a.iter().all(|i| *i < 3));
+    }
+
+    #[test]
+    fn weighted_never_picks_zero_weights() {
+        let weights = HashMap::from([("a".to_string(), 0.0), ("c".to_string(), 0.0)]);
+        assert_eq!(picks(&mut Weighted::make(weights, 7), 8), vec![1; 8]);
+    }
+
+    struct OutOfRange {}
+    impl SchedulingPolicy for OutOfRange {
+        fn select(&mut self, _candidates: &[Candidate]) -> usize {
+            usize::MAX
+        }
+    }
+
+    #[test]
+    fn out_of_range_selection_is_clamped() {
+        let net = ["a", "b"].iter().fold(Net::make(), |net, t| {
+            net.set_start_tokens(&t.to_uppercase(), vec![Token::new(0u32)])
+                .add_typed_transition(t, || Countdown {})
+                .place_to_transition(&t.to_uppercase(), "n", t)
+                .transition_to_place(t, "n", &t.to_uppercase())
+                .transition_to_place(t, "done", "D")
+        });
+        let (mut wc, _exit_tx) = work_cluster(net);
+        wc.set_scheduling_policy(Box::new(OutOfRange {}));
+        wc.begin();
+        wc.fire();
+        assert_eq!(tokens::<u32>(&wc.take_places(), "D"), vec![0, 0]);
+    }
+}
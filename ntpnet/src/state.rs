@@ -1,104 +1,297 @@
-use bimap::BiMap;
 use crossbeam_channel::{Receiver, Select, Sender};
 use std::any::TypeId;
-use std::collections::{HashMap, HashSet, VecDeque};
+use std::collections::{BTreeMap, HashMap, VecDeque};
 use std::mem;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use crate::Token;
-use plotmux::plotsink::PlotSink;
+use crate::{
+    capacity::Bound, observer::Observers, trace::Tracer, Checkpoint, CheckpointError,
+    QueueDiscipline, Token, TokenRegistry, TransitionFault,
+};
+use itertools::Itertools;
+
+pub(crate) type RejectedGuards =
+    HashMap<(String, String, usize), BTreeMap<(String, TypeId), usize>>;
+pub(crate) type PoppedTokens = HashMap<(String, TypeId), usize>;
+pub(crate) type PushedTokens = HashMap<(String, TypeId), (&'static str, usize)>;
 
 #[derive(Debug)]
 pub struct StateDelta {
-    sub: HashSet<(String, TypeId)>,
-    add: HashMap<(String, TypeId), &'static str>,
+    sub: PoppedTokens,
+    add: PushedTokens,
+    rejected: RejectedGuards,
+    fault: Option<TransitionFault>,
+    in_flight: i64,
 }
 impl StateDelta {
-    fn make() -> Self {
+    pub(crate) fn make() -> Self {
         Self {
-            sub: HashSet::new(),
+            sub: HashMap::new(),
             add: HashMap::new(),
+            rejected: HashMap::new(),
+            fault: None,
+            in_flight: 0,
         }
     }
+    fn is_empty(&self) -> bool {
+        self.sub.is_empty()
+            && self.add.is_empty()
+            && self.rejected.is_empty()
+            && self.fault.is_none()
+            && self.in_flight == 0
+    }
     fn pop(&mut self, p_ty: &(String, TypeId)) {
-        self.sub.insert(p_ty.clone());
+        *self.sub.entry(p_ty.clone()).or_insert(0) += 1;
     }
-    fn push(&mut self, p_ty: &(String, TypeId), ty_name: &'static str) {
-        self.add.insert((p_ty.0.clone(), p_ty.1.clone()), ty_name);
+    pub(crate) fn push(&mut self, p_ty: &(String, TypeId), ty_name: &'static str) {
+        self.add.entry(p_ty.clone()).or_insert((ty_name, 0)).1 += 1;
     }
     pub fn take(
         self,
     ) -> (
-        HashSet<(String, TypeId)>,
-        HashMap<(String, TypeId), &'static str>,
+        PoppedTokens,
+        PushedTokens,
+        RejectedGuards,
+        Option<TransitionFault>,
+        i64,
     ) {
-        (self.sub, self.add)
+        (
+            self.sub,
+            self.add,
+            self.rejected,
+            self.fault,
+            self.in_flight,
+        )
     }
 }
 
+pub(crate) type CheckpointRequest = (
+    Arc<TokenRegistry>,
+    Sender<Result<Checkpoint, CheckpointError>>,
+);
+
 pub enum StateBlockable {
     Tokens((TypeId, Token)),
     Terminate(()),
+    Pause(()),
+    Resume(()),
+    Wake(usize),
+    Released(()),
+    Barrier(Sender<()>),
+    Checkpoint(CheckpointRequest),
 }
 
 #[derive(Debug)]
 pub struct State {
     places: HashMap<String, HashMap<TypeId, VecDeque<Token>>>,
-    input_places_idx: BiMap<String, usize>,
+    input_place_names: Vec<String>,
     receivers: Vec<Receiver<StateBlockable>>,
     output_places: HashMap<String, Sender<StateBlockable>>,
+    subscriptions: HashMap<(String, TypeId), Sender<StateBlockable>>,
+    wake_tx: Sender<StateBlockable>,
+    woken: Vec<usize>,
     state: HashMap<(String, TypeId), (usize, String)>,
-    state_exists: HashSet<(String, TypeId)>,
+    pending: Vec<((String, TypeId), Token)>,
+    timers: Vec<(String, Duration, Instant)>,
+    bounds: HashMap<String, Bound>,
+    disciplines: HashMap<String, QueueDiscipline>,
+    paused: bool,
+    tracer: Option<Tracer>,
+    observers: (usize, Observers),
+    barriers: Vec<Sender<()>>,
+    checkpoints: Vec<CheckpointRequest>,
     state_delta: StateDelta,
     state_delta_notification: Sender<StateDelta>,
 }
 impl State {
+    #[allow(clippy::too_many_arguments)]
     pub fn make(
-        places: HashMap<String, HashMap<TypeId, VecDeque<Token>>>,
+        mut places: HashMap<String, HashMap<TypeId, VecDeque<Token>>>,
+        timers: HashMap<String, Duration>,
+        bounds: HashMap<String, Bound>,
+        disciplines: HashMap<String, QueueDiscipline>,
         input_places: HashMap<String, Receiver<StateBlockable>>,
         output_places: HashMap<String, Sender<StateBlockable>>,
         state_delta: Sender<StateDelta>,
+        wake: (Sender<StateBlockable>, Receiver<StateBlockable>),
         exit_rx: Receiver<StateBlockable>,
     ) -> Self {
+        let now = Instant::now();
+        let mut pending = vec![];
+        let mut delta = StateDelta::make();
+        for (place_name, ty_v) in places.iter_mut() {
+            let discipline = disciplines
+                .get(place_name)
+                .cloned()
+                .unwrap_or(QueueDiscipline::Fifo);
+            for (ty, v) in ty_v.iter_mut() {
+                let (released, delayed): (VecDeque<_>, VecDeque<_>) = v
+                    .drain(..)
+                    .partition(|t| t.release().is_none_or(|r| r <= now));
+                *v = released;
+                for t in delayed {
+                    pending.push(((place_name.clone(), *ty), t));
+                }
+                for _ in 0..discipline.arrange(v) {
+                    delta.pop(&(place_name.clone(), *ty));
+                }
+            }
+            if let Some(bound) = bounds.get(place_name) {
+                let held = ty_v.values().map(|v| v.len()).sum::<usize>()
+                    + pending.iter().filter(|((p, _), _)| p == place_name).count();
+                for _ in 0..held {
+                    bound.acquire();
+                }
+            }
+        }
         let state = {
             let mut state = HashMap::new();
             for (place_name, ty_v) in places.iter() {
                 for (ty, v) in ty_v.iter() {
-                    state.insert(
-                        (place_name.clone(), ty.clone()),
-                        (v.len(), (*v[0]).type_name().into()),
-                    );
+                    if let Some(t) = v.front() {
+                        state.insert(
+                            (place_name.clone(), *ty),
+                            (v.len(), (**t).type_name().into()),
+                        );
+                    }
                 }
             }
             state
         };
-        let state_exists = state
-            .iter()
-            .filter_map(|(k, v)| if v.0 > 0 { Some(k.clone()) } else { None })
-            .collect::<_>();
-        let mut input_places_idx = BiMap::new();
-        let mut input_places = input_places
-            .into_iter()
-            .enumerate()
-            .map(|(i, (name, rx))| {
-                input_places_idx.insert(name.clone(), i);
-                rx
-            })
-            .collect::<Vec<_>>();
+        let (input_place_names, mut input_places): (Vec<_>, Vec<_>) =
+            input_places.into_iter().unzip();
+        let (wake_tx, wake_rx) = wake;
+        input_places.push(wake_rx);
         input_places.push(exit_rx);
         Self {
-            places: places,
-            input_places_idx: input_places_idx,
+            places,
+            input_place_names,
             receivers: input_places,
-            output_places: output_places,
-            state: state,
-            state_exists: state_exists,
-            state_delta: StateDelta::make(),
+            output_places,
+            subscriptions: HashMap::new(),
+            wake_tx,
+            woken: vec![],
+            state,
+            pending,
+            timers: timers
+                .into_iter()
+                .map(|(place, period)| (place, period, now + period))
+                .collect(),
+            bounds,
+            disciplines,
+            paused: false,
+            tracer: None,
+            observers: (0, Observers::default()),
+            barriers: vec![],
+            checkpoints: vec![],
+            state_delta: delta,
             state_delta_notification: state_delta,
         }
     }
-    pub fn take_places(self) -> HashMap<String, HashMap<TypeId, VecDeque<Token>>> {
+    pub fn take_places(mut self) -> HashMap<String, HashMap<TypeId, VecDeque<Token>>> {
+        for (p_name, rx) in self.input_place_names.iter().zip(self.receivers.iter()) {
+            while let Ok(send_thing) = rx.try_recv() {
+                if let StateBlockable::Tokens((ty, token)) = send_thing {
+                    self.pending.push(((p_name.clone(), ty), token));
+                }
+            }
+        }
+        for ((place, ty), t) in self.pending {
+            self.places
+                .get_mut(&place)
+                .unwrap()
+                .entry(ty)
+                .or_default()
+                .push_back(t);
+        }
         self.places
     }
+    pub fn add_input(&mut self, place: String, rx: Receiver<StateBlockable>) {
+        self.receivers.insert(self.input_place_names.len(), rx);
+        self.input_place_names.push(place);
+    }
+    pub fn subscribe(&mut self, p_ty: (String, TypeId), tx: Sender<StateBlockable>) {
+        self.subscriptions.insert(p_ty, tx);
+    }
+    pub fn trace(&mut self, tracer: Tracer) {
+        self.tracer = Some(tracer);
+    }
+    pub fn observe(&mut self, index: usize, observers: Observers) {
+        self.observers = (index, observers);
+    }
+    fn observe_count(&self, p_ty: &(String, TypeId), push: bool) {
+        let (index, observers) = &self.observers;
+        let (count, ty_name) = &self.state[p_ty];
+        observers.notify(|o| {
+            if push {
+                o.on_token_push(*index, &p_ty.0, ty_name, *count)
+            } else {
+                o.on_token_pop(*index, &p_ty.0, ty_name, *count)
+            }
+        });
+    }
+    pub fn waker(&self) -> Sender<StateBlockable> {
+        self.wake_tx.clone()
+    }
+    pub fn take_woken(&mut self) -> Vec<usize> {
+        mem::take(&mut self.woken)
+    }
+    pub fn begin_async(&mut self) {
+        self.state_delta.in_flight += 1;
+    }
+    pub fn end_async(&mut self) {
+        self.state_delta.in_flight -= 1;
+    }
+    pub fn paused(&self) -> bool {
+        self.paused
+    }
+    pub fn timed(&self, place: &String) -> bool {
+        self.timers.iter().any(|(p, _, _)| p == place)
+    }
+    fn next_deadline(&self) -> Option<Instant> {
+        self.timers
+            .iter()
+            .map(|(_, _, next)| *next)
+            .chain(self.pending.iter().filter_map(|(_, t)| t.release()))
+            .min()
+    }
+    fn tick(&mut self) {
+        let now = Instant::now();
+        let mut ticked = false;
+        for i in 0..self.timers.len() {
+            let (place, period, next) = self.timers[i].clone();
+            if next <= now {
+                self.timers[i].2 = if next + period > now {
+                    next + period
+                } else {
+                    now + period
+                };
+                self.push(&(place, TypeId::of::<Instant>()), Token::new(next));
+                ticked = true;
+            }
+        }
+        if ticked {
+            self.state_delta_complete();
+        }
+        if self
+            .pending
+            .iter()
+            .any(|(_, t)| t.release().unwrap() <= now)
+        {
+            let (released, pending): (Vec<_>, Vec<_>) = self
+                .pending
+                .drain(..)
+                .partition(|(_, t)| t.release().unwrap() <= now);
+            self.pending = pending;
+            for (p_ty, t) in released.into_iter().sorted_by_key(|(_, t)| t.release()) {
+                self.insert(&p_ty, t);
+            }
+        }
+    }
+    pub fn wait_set(&self) -> (Vec<Receiver<StateBlockable>>, Option<Instant>) {
+        (self.receivers.clone(), self.next_deadline())
+    }
     pub fn block_rx(&mut self) -> bool {
         let mut rxs = vec![];
         mem::swap(&mut self.receivers, &mut rxs);
@@ -106,21 +299,65 @@ impl State {
         for rs in rxs.as_slice() {
             sel.recv(rs);
         }
-        let index = sel.ready();
-        let exit = if let Ok(send_thing) = rxs[index].recv() {
-            match send_thing {
-                StateBlockable::Tokens((ty, token)) => {
-                    let p_name = self.input_places_idx.get_by_right(&index).unwrap().clone();
-                    self.push_local(&(p_name, ty), token);
-                    false
+        let index = match self.next_deadline() {
+            Some(deadline) => match sel.ready_deadline(deadline) {
+                Ok(index) => index,
+                Err(_) => {
+                    mem::swap(&mut self.receivers, &mut rxs);
+                    return false;
+                }
+            },
+            None => sel.ready(),
+        };
+        let received = rxs[index].recv();
+        mem::swap(&mut self.receivers, &mut rxs);
+        match received {
+            Ok(send_thing) => self.receive(index, send_thing),
+            Err(_) => self.disconnect(index),
+        }
+    }
+    fn receive(&mut self, index: usize, send_thing: StateBlockable) -> bool {
+        match send_thing {
+            StateBlockable::Tokens((ty, mut token)) => {
+                if let (Some(tracer), Some(flow)) = (&self.tracer, token.take_flow()) {
+                    tracer.flow_end(flow);
                 }
-                StateBlockable::Terminate(_) => true,
+                let p_name = self.input_place_names[index].clone();
+                self.push_local(&(p_name, ty), token);
+                false
             }
+            StateBlockable::Terminate(_) => true,
+            StateBlockable::Pause(_) => {
+                self.paused = true;
+                false
+            }
+            StateBlockable::Resume(_) => {
+                self.paused = false;
+                false
+            }
+            StateBlockable::Wake(id) => {
+                self.woken.push(id);
+                false
+            }
+            StateBlockable::Released(_) => false,
+            StateBlockable::Barrier(tx) => {
+                self.barriers.push(tx);
+                false
+            }
+            StateBlockable::Checkpoint(request) => {
+                self.checkpoints.push(request);
+                false
+            }
+        }
+    }
+    fn disconnect(&mut self, index: usize) -> bool {
+        if index < self.input_place_names.len() {
+            self.input_place_names.remove(index);
+            self.receivers.remove(index);
+            false
         } else {
             true
-        };
-        mem::swap(&mut self.receivers, &mut rxs);
-        exit
+        }
     }
     pub fn try_rx(&mut self) -> bool {
         let mut rxs = vec![];
@@ -130,88 +367,273 @@ impl State {
             sel.recv(rs);
         }
         let mut exit = false;
+        let mut disconnected = None;
         while let Ok(index) = sel.try_ready() {
-            exit = if let Ok(send_thing) = rxs[index].recv() {
-                match send_thing {
-                    StateBlockable::Tokens((ty, token)) => {
-                        let p_name = self.input_places_idx.get_by_right(&index).unwrap().clone();
-                        self.push_local(&(p_name, ty), token);
-                        false
-                    }
-                    StateBlockable::Terminate(_) => true,
+            exit = match rxs[index].recv() {
+                Ok(send_thing) => self.receive(index, send_thing),
+                Err(_) => {
+                    disconnected = Some(index);
+                    break;
                 }
-            } else {
-                break;
             };
+            if exit {
+                break;
+            }
         }
         mem::swap(&mut self.receivers, &mut rxs);
+        if let Some(index) = disconnected {
+            exit = self.disconnect(index);
+        }
         exit
     }
-    pub fn binary(
-        &mut self,
-        plot: Option<(&mut PlotSink, f64)>,
-    ) -> (bool, &HashSet<(String, TypeId)>) {
+    pub fn refresh(&mut self) -> bool {
         let exit = self.try_rx();
-        if let Some((plot, time)) = plot {
-            for ((place, _ty), (len, ty_name)) in &self.state {
-                plot.plot_series_2d(
-                    "local state",
-                    &format!("{}/{}", place, ty_name),
-                    time,
-                    *len as f64,
-                );
-            }
+        self.tick();
+        if !self.state_delta.is_empty() {
+            self.state_delta_complete();
         }
-        (exit, &self.state_exists)
+        exit
     }
-    pub fn pop(&mut self, p_ty: &(String, TypeId)) -> Token {
-        self.state_delta.pop(p_ty);
-        *&mut self.state.get_mut(p_ty).unwrap().0 -= 1;
-        if self.state[p_ty].0 == 0 {
-            self.state_exists.remove(p_ty);
+    pub fn settle(&mut self) {
+        for tx in mem::take(&mut self.barriers) {
+            let _ = tx.send(());
+        }
+        for (registry, tx) in mem::take(&mut self.checkpoints) {
+            let _ = tx.send(self.checkpoint(&registry));
+        }
+    }
+    fn checkpoint(&self, registry: &TokenRegistry) -> Result<Checkpoint, CheckpointError> {
+        let mut checkpoint = Checkpoint::from_places(&self.places, registry)?;
+        let now = Instant::now();
+        for ((place, ty), group) in &self
+            .pending
+            .iter()
+            .sorted_by_key(|((place, ty), t)| (place, *ty, t.release()))
+            .group_by(|(p_ty, _)| p_ty)
+        {
+            let group = group.map(|(_, t)| t).collect::<Vec<_>>();
+            let mut queue = registry.encode(place, ty, group.iter().cloned())?;
+            queue.delays = group
+                .iter()
+                .map(|t| {
+                    t.release()
+                        .map_or(Duration::ZERO, |r| r.saturating_duration_since(now))
+                })
+                .collect();
+            checkpoint
+                .places
+                .entry(place.clone())
+                .or_insert(vec![])
+                .push(queue);
+        }
+        Ok(checkpoint)
+    }
+    pub fn count(&self, p_ty: &(String, TypeId)) -> usize {
+        self.state.get(p_ty).map_or(0, |s| s.0)
+    }
+    pub fn empty(&self, place: &String) -> bool {
+        self.places
+            .get(place)
+            .is_none_or(|qs| qs.values().all(|q| q.is_empty()))
+            && !self.pending.iter().any(|((p, _), _)| p == place)
+    }
+    pub fn queue(&self, p_ty: &(String, TypeId)) -> Option<&VecDeque<Token>> {
+        self.places.get(&p_ty.0).and_then(|qs| qs.get(&p_ty.1))
+    }
+    pub fn disable_blocking(&mut self) {
+        for bound in self.bounds.values_mut() {
+            bound.disable_blocking();
+        }
+    }
+    pub fn capacity(&self, place: &str) -> Option<usize> {
+        self.bounds.get(place).map(|bound| bound.capacity())
+    }
+    pub fn has_room(&self, place: &str, n: usize) -> bool {
+        self.bounds.get(place).is_none_or(|bound| bound.has_room(n))
+    }
+    pub fn pop(&mut self, p_ty: &(String, TypeId), idx: usize) -> Token {
+        if let Some(bound) = self.bounds.get(&p_ty.0) {
+            bound.release();
         }
+        self.state_delta.pop(p_ty);
+        self.state.get_mut(p_ty).unwrap().0 -= 1;
+        self.observe_count(p_ty, false);
         self.places
             .get_mut(&p_ty.0)
             .unwrap()
             .get_mut(&p_ty.1)
             .unwrap()
-            .pop_front()
+            .remove(idx)
             .unwrap()
     }
     fn push_local(&mut self, p_ty: &(String, TypeId), t: Token) {
-        if !self.places[&p_ty.0].contains_key(&p_ty.1) {
+        if t.release().is_some_and(|r| r > Instant::now()) {
+            self.pending.push((p_ty.clone(), t));
+        } else {
+            self.insert(p_ty, t);
+        }
+    }
+    fn insert(&mut self, p_ty: &(String, TypeId), t: Token) {
+        if !self.state.contains_key(p_ty) {
             self.places
                 .get_mut(&p_ty.0)
                 .unwrap()
-                .insert(p_ty.1.clone(), VecDeque::new());
+                .entry(p_ty.1)
+                .or_default();
             self.state
                 .insert(p_ty.clone(), (0, (*t).type_name().to_string()));
         }
-        self.places
-            .get_mut(&p_ty.0)
-            .unwrap()
-            .get_mut(&p_ty.1)
-            .unwrap()
-            .push_back(t);
-        *&mut self.state.get_mut(p_ty).unwrap().0 += 1;
-        if !self.state_exists.contains(p_ty) {
-            self.state_exists.insert(p_ty.clone());
+        let discipline = self
+            .disciplines
+            .get(&p_ty.0)
+            .cloned()
+            .unwrap_or(QueueDiscipline::Fifo);
+        let dropped = discipline.enqueue(
+            self.places
+                .get_mut(&p_ty.0)
+                .unwrap()
+                .get_mut(&p_ty.1)
+                .unwrap(),
+            t,
+        );
+        self.state.get_mut(p_ty).unwrap().0 += 1;
+        self.observe_count(p_ty, true);
+        for _ in 0..dropped {
+            self.state.get_mut(p_ty).unwrap().0 -= 1;
+            self.state_delta.pop(p_ty);
+            if let Some(bound) = self.bounds.get(&p_ty.0) {
+                bound.release();
+            }
+            self.observe_count(p_ty, false);
         }
     }
     pub fn push(&mut self, p_ty: &(String, TypeId), t: Token) {
+        let t = match self.subscriptions.get(p_ty) {
+            Some(subscription) => match subscription.send(StateBlockable::Tokens((p_ty.1, t))) {
+                Ok(()) => return,
+                Err(e) => match e.into_inner() {
+                    StateBlockable::Tokens((_, t)) => t,
+                    _ => unreachable!(),
+                },
+            },
+            None => t,
+        };
+        if let Some(bound) = self.bounds.get(&p_ty.0) {
+            bound.acquire();
+        }
         self.state_delta.push(p_ty, (*t).type_name());
         if let Some(out_place) = self.output_places.get_mut(&p_ty.0) {
-            out_place
-                .send(StateBlockable::Tokens((p_ty.1.clone(), t)))
-                .unwrap();
+            let t = match &self.tracer {
+                Some(tracer) => t.with_flow(tracer.flow_start()),
+                None => t,
+            };
+            if let Err(e) = out_place.send(StateBlockable::Tokens((p_ty.1, t))) {
+                if let StateBlockable::Tokens((_, t)) = e.into_inner() {
+                    self.insert(p_ty, t);
+                }
+            }
         } else {
             self.push_local(p_ty, t);
         }
     }
+    pub fn reject(
+        &mut self,
+        guard: (String, String, usize),
+        snapshot: BTreeMap<(String, TypeId), usize>,
+    ) {
+        self.state_delta.rejected.insert(guard, snapshot);
+    }
+    pub fn fault(&mut self, fault: TransitionFault) {
+        self.state_delta.fault = Some(fault);
+    }
     pub fn state_delta_complete(&mut self) {
         let mut temp = StateDelta::make();
         mem::swap(&mut temp, &mut self.state_delta);
-        self.state_delta_notification.send(temp).unwrap();
+        let _ = self.state_delta_notification.send(temp);
         self.state_delta = StateDelta::make();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::{countdown::Countdown, run, run_for, tokens};
+    use crate::{Net, Token};
+    use std::time::{Duration, Instant};
+
+    fn net(inhibiting: Vec<Token>) -> Net {
+        Net::make()
+            .set_start_tokens("N", vec![Token::new(0u32)])
+            .set_start_tokens("X", inhibiting)
+            .add_typed_transition("c", || Countdown {})
+            .place_to_transition("N", "n", "c")
+            .transition_to_place("c", "n", "N")
+            .transition_to_place("c", "done", "D")
+            .inhibit("X", "c")
+    }
+
+    #[test]
+    fn inhibitor_blocks_while_place_has_tokens() {
+        assert_eq!(tokens::<u32>(&run(net(vec![])), "D"), vec![0]);
+        assert!(tokens::<u32>(&run(net(vec![Token::new(())])), "D").is_empty());
+    }
+
+    #[test]
+    fn inhibitor_counts_delayed_tokens() {
+        let release = Instant::now() + Duration::from_secs(3600);
+        let places = run(net(vec![Token::new(()).with_release(release)]));
+        assert!(tokens::<u32>(&places, "D").is_empty());
+        assert_eq!(tokens::<()>(&places, "X").len(), 1);
+    }
+
+    fn chain(n: Token) -> Net {
+        Net::make()
+            .set_start_tokens("N", vec![n])
+            .add_typed_transition("c", || Countdown {})
+            .add_typed_transition("d", || Countdown {})
+            .place_to_transition("N", "n", "c")
+            .transition_to_place("c", "n", "N")
+            .transition_to_place("c", "done", "D")
+            .place_to_transition("D", "n", "d")
+            .transition_to_place("d", "n", "D")
+            .transition_to_place("d", "done", "E")
+    }
+
+    #[test]
+    fn timer_places_emit_instants() {
+        let period = Duration::from_millis(10);
+        let start = Instant::now();
+        let places = run_for(
+            Net::make().add_timer("T", period),
+            Duration::from_millis(55),
+        );
+        let end = Instant::now();
+        let ticks = tokens::<Instant>(&places, "T");
+        assert!(!ticks.is_empty());
+        assert!(ticks[0] >= start + period);
+        assert!(ticks.windows(2).all(|w| w[1] >= w[0] + period));
+        assert!(ticks.iter().all(|t| *t <= end));
+    }
+
+    #[test]
+    fn delayed_outputs_are_hidden_until_released() {
+        let net = |delay| chain(Token::new(0u32)).set_delay("c", delay);
+        let hour = Duration::from_secs(3600);
+        assert!(tokens::<u32>(&run(net(hour)), "E").is_empty());
+        let delay = Duration::from_millis(20);
+        let start = Instant::now();
+        let places = run_for(net(delay), hour);
+        assert!(start.elapsed() >= delay);
+        assert_eq!(tokens::<u32>(&places, "E"), vec![0]);
+    }
+
+    #[test]
+    fn tokens_wait_for_their_release_time() {
+        let net = |release| chain(Token::new(0u32).with_release(release));
+        let hour = Duration::from_secs(3600);
+        assert!(tokens::<u32>(&run(net(Instant::now() + hour)), "E").is_empty());
+        let release = Instant::now() + Duration::from_millis(20);
+        let places = run_for(net(release), hour);
+        assert!(Instant::now() >= release);
+        assert_eq!(tokens::<u32>(&places, "E"), vec![0]);
+    }
+}
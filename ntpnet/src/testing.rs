@@ -0,0 +1,168 @@
+use crossbeam_channel::{bounded, unbounded, Sender};
+use plotmux::plotmux::{ClientMode, PlotMux};
+use std::any::TypeId;
+use std::collections::{HashMap, VecDeque};
+use std::mem;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::{
+    capacity::Slots, state::StateBlockable, work_cluster::WorkCluster, Net, NetError,
+    ReactorHandle, Token,
+};
+
+pub type Places = HashMap<String, HashMap<TypeId, VecDeque<Token>>>;
+
+pub fn work_cluster(net: Net) -> (WorkCluster, Sender<StateBlockable>) {
+    try_work_cluster(net).unwrap_or_else(|errors| panic!("{:?}", errors))
+}
+
+pub fn try_work_cluster(net: Net) -> Result<(WorkCluster, Sender<StateBlockable>), Vec<NetError>> {
+    let plot_sink = Arc::new(Mutex::new(
+        PlotMux::make(ClientMode::Local()).add_plot_sink("test"),
+    ));
+    mem::forget(plot_sink.clone());
+    let slots = net
+        .capacities
+        .iter()
+        .map(|(p, (capacity, _))| {
+            let (tx, rx) = bounded(*capacity);
+            let notify = vec![];
+            (p.clone(), Slots { tx, rx, notify })
+        })
+        .collect();
+    let (exit_tx, exit_rx) = unbounded();
+    let wc = WorkCluster::try_make(
+        net,
+        HashMap::new(),
+        HashMap::new(),
+        slots,
+        plot_sink,
+        unbounded().0,
+        unbounded(),
+        exit_rx,
+    )?;
+    Ok((wc, exit_tx))
+}
+
+pub fn reactor_handle(net: Net) -> ReactorHandle {
+    let (wc, exit_tx) = work_cluster(net);
+    ReactorHandle::make(vec![exit_tx], thread::spawn(move || wc.run()))
+}
+
+pub fn run(net: Net) -> Places {
+    let (mut wc, _exit_tx) = work_cluster(net);
+    wc.begin();
+    wc.fire();
+    wc.take_places()
+}
+
+pub fn run_for(net: Net, duration: Duration) -> Places {
+    let (mut wc, _exit_tx) = work_cluster(net);
+    let end = Instant::now() + duration;
+    wc.begin();
+    loop {
+        wc.fire();
+        match wc.wait_set().1 {
+            Some(deadline) if deadline < end => {
+                thread::sleep(deadline.saturating_duration_since(Instant::now()))
+            }
+            _ => break,
+        }
+    }
+    wc.take_places()
+}
+
+pub fn tokens<T: Clone + 'static>(places: &Places, place: &str) -> Vec<T> {
+    places
+        .get(place)
+        .and_then(|tys| tys.get(&TypeId::of::<T>()))
+        .into_iter()
+        .flatten()
+        .map(|t| t.downcast_ref::<T>().unwrap().clone())
+        .collect()
+}
+
+pub mod countdown {
+    #[derive(crate::TransitionInputTokensMacro)]
+    pub struct N {
+        pub n: u32,
+    }
+    #[derive(crate::TransitionOutputTokensMacro)]
+    pub struct More {
+        pub n: u32,
+    }
+    #[derive(crate::TransitionOutputTokensMacro)]
+    pub struct Done {
+        pub done: u32,
+    }
+    #[derive(crate::Transition)]
+    #[ntpnet_transition(count: Input(N) -> Output(More, Done))]
+    pub struct Countdown {}
+    impl Countdown {
+        fn count(&mut self, i: Input) -> Output {
+            let Input::N(N { n }) = i;
+            if n == 0 {
+                Output::Done(Done { done: n })
+            } else {
+                Output::More(More { n: n - 1 })
+            }
+        }
+    }
+}
+
+pub mod batch {
+    #[derive(crate::TransitionInputTokensMacro)]
+    pub struct Pair {
+        #[ntpnet_weighted]
+        pub n: [u32; 2],
+    }
+    #[derive(crate::TransitionInputTokensMacro)]
+    pub struct One {
+        pub n: u32,
+    }
+    #[derive(crate::TransitionOutputTokensMacro)]
+    pub struct Total {
+        pub total: u32,
+    }
+    #[derive(crate::TransitionOutputTokensMacro)]
+    pub struct Copies {
+        #[ntpnet_weighted]
+        pub copies: Vec<u32>,
+    }
+    #[derive(crate::Transition)]
+    #[ntpnet_transition(sum: SumIn(Pair) -> SumOut(Total))]
+    pub struct Sum {}
+    impl Sum {
+        fn sum(&mut self, i: SumIn) -> SumOut {
+            let SumIn::Pair(Pair { n }) = i;
+            SumOut::Total(Total { total: n[0] + n[1] })
+        }
+    }
+    #[derive(crate::Transition)]
+    #[ntpnet_transition(spread: SpreadIn(One) -> SpreadOut(Copies))]
+    pub struct Spread {}
+    impl Spread {
+        fn spread(&mut self, i: SpreadIn) -> SpreadOut {
+            let SpreadIn::One(One { n }) = i;
+            SpreadOut::Copies(Copies {
+                copies: vec![n; n as usize],
+            })
+        }
+    }
+    #[derive(crate::Transition)]
+    #[ntpnet_transition(pair: PairIn(Pair) -> PairOut(Total))]
+    #[ntpnet_transition(one: OneIn(One) -> OneOut(Total))]
+    pub struct Batch {}
+    impl Batch {
+        fn pair(&mut self, i: PairIn) -> PairOut {
+            let PairIn::Pair(Pair { n }) = i;
+            PairOut::Total(Total { total: n[0] + n[1] })
+        }
+        fn one(&mut self, i: OneIn) -> OneOut {
+            let OneIn::One(One { n }) = i;
+            OneOut::Total(Total { total: n })
+        }
+    }
+}
@@ -0,0 +1,296 @@
+use crossbeam_channel::{unbounded, Receiver, Select, Sender};
+use std::any::TypeId;
+use std::collections::{HashMap, VecDeque};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Instant;
+
+use crate::{state::StateBlockable, work_cluster::WorkCluster, Token};
+
+type Places = HashMap<String, HashMap<TypeId, VecDeque<Token>>>;
+pub type WorkClusterMaker = Box<dyn FnOnce() -> WorkCluster + Send>;
+
+enum Parked {
+    Idle(Vec<Receiver<StateBlockable>>, Option<Instant>),
+    Done(Places),
+    Failed,
+}
+
+struct Idle {
+    index: usize,
+    receivers: Vec<Receiver<StateBlockable>>,
+    deadline: Option<Instant>,
+}
+
+fn fire(wc: &mut WorkCluster) -> Option<bool> {
+    catch_unwind(AssertUnwindSafe(|| wc.fire())).ok()
+}
+
+enum Slot {
+    Unmade(WorkClusterMaker),
+    Made(Box<WorkCluster>),
+}
+
+pub struct ThreadPool {
+    workers: Vec<JoinHandle<()>>,
+    ready_tx: Sender<usize>,
+    parked_rx: Receiver<(usize, Parked)>,
+    work_cluster_count: usize,
+}
+impl ThreadPool {
+    pub fn make(work_clusters: Vec<WorkClusterMaker>, threads: usize) -> Self {
+        let work_cluster_count = work_clusters.len();
+        let slots = Arc::new(
+            work_clusters
+                .into_iter()
+                .map(|make_wc| Mutex::new(Some(Slot::Unmade(make_wc))))
+                .collect::<Vec<_>>(),
+        );
+        let (ready_tx, ready_rx) = unbounded::<usize>();
+        let (parked_tx, parked_rx) = unbounded::<(usize, Parked)>();
+        for index in 0..work_cluster_count {
+            let _ = ready_tx.send(index);
+        }
+        let workers = (0..threads.max(1))
+            .map(|i| {
+                let slots = slots.clone();
+                let ready_rx = ready_rx.clone();
+                let parked_tx = parked_tx.clone();
+                thread::Builder::new()
+                    .name(format!("pool-worker-{}", i))
+                    .spawn(move || {
+                        for index in ready_rx.iter() {
+                            let slot = slots[index].lock().unwrap().take();
+                            let mut wc = match slot {
+                                Some(Slot::Unmade(make_wc)) => {
+                                    match catch_unwind(AssertUnwindSafe(make_wc)) {
+                                        Ok(mut wc) => {
+                                            wc.begin();
+                                            wc
+                                        }
+                                        Err(_) => {
+                                            let _ = parked_tx.send((index, Parked::Failed));
+                                            continue;
+                                        }
+                                    }
+                                }
+                                Some(Slot::Made(wc)) => *wc,
+                                None => continue,
+                            };
+                            let parked = match fire(&mut wc) {
+                                Some(false) => {
+                                    let (receivers, deadline) = wc.wait_set();
+                                    *slots[index].lock().unwrap() = Some(Slot::Made(Box::new(wc)));
+                                    Parked::Idle(receivers, deadline)
+                                }
+                                Some(true) => Parked::Done(wc.take_places()),
+                                None => {
+                                    wc.println(&format!("failed to fire work-cluster-{}", index));
+                                    Parked::Failed
+                                }
+                            };
+                            if parked_tx.send((index, parked)).is_err() {
+                                break;
+                            }
+                        }
+                    })
+                    .unwrap_or_else(|_| panic!("unable to spawn pool-worker-{} thread", i))
+            })
+            .collect();
+        Self {
+            workers,
+            ready_tx,
+            parked_rx,
+            work_cluster_count,
+        }
+    }
+    fn ready(&self, index: usize) {
+        let _ = self.ready_tx.send(index);
+    }
+    pub fn run(self) -> Vec<Option<Places>> {
+        let mut end_states = (0..self.work_cluster_count)
+            .map(|_| None)
+            .collect::<Vec<_>>();
+        let mut running = self.work_cluster_count;
+        let mut idle: Vec<Idle> = vec![];
+        while running > 0 {
+            let ready = {
+                let mut sel = Select::new();
+                sel.recv(&self.parked_rx);
+                for i in &idle {
+                    for rx in &i.receivers {
+                        sel.recv(rx);
+                    }
+                }
+                match idle.iter().filter_map(|i| i.deadline).min() {
+                    Some(deadline) => sel.ready_deadline(deadline).ok(),
+                    None => Some(sel.ready()),
+                }
+            };
+            match ready {
+                Some(0) => {
+                    let (index, parked) =
+                        self.parked_rx.recv().expect("thread pool workers exited");
+                    match parked {
+                        Parked::Idle(receivers, deadline) => idle.push(Idle {
+                            index,
+                            receivers,
+                            deadline,
+                        }),
+                        Parked::Done(places) => {
+                            end_states[index] = Some(places);
+                            running -= 1;
+                        }
+                        Parked::Failed => running -= 1,
+                    }
+                }
+                Some(mut op) => {
+                    op -= 1;
+                    let pos = idle
+                        .iter()
+                        .position(|i| {
+                            if op < i.receivers.len() {
+                                true
+                            } else {
+                                op -= i.receivers.len();
+                                false
+                            }
+                        })
+                        .unwrap();
+                    let i = idle.swap_remove(pos);
+                    self.ready(i.index);
+                }
+                None => {
+                    let now = Instant::now();
+                    let (due, waiting): (Vec<_>, Vec<_>) = idle
+                        .into_iter()
+                        .partition(|i| i.deadline.is_some_and(|deadline| deadline <= now));
+                    idle = waiting;
+                    for i in due {
+                        self.ready(i.index);
+                    }
+                }
+            }
+        }
+        drop(self.ready_tx);
+        for worker in self.workers {
+            let _ = worker.join();
+        }
+        end_states
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scheduling::{Candidate, SchedulingPolicy};
+    use crate::testing::countdown::{Countdown, N};
+    use crate::testing::{tokens, work_cluster};
+    use crate::Net;
+    use std::time::Duration;
+
+    struct Panicking {}
+    impl SchedulingPolicy for Panicking {
+        fn select(&mut self, _candidates: &[Candidate]) -> usize {
+            panic!("select")
+        }
+    }
+
+    #[derive(crate::TransitionOutputTokensMacro)]
+    struct Signalled {
+        signalled: Option<u32>,
+    }
+    #[derive(crate::Transition)]
+    #[ntpnet_transition(wait: WaitIn(N) -> WaitOut(Signalled))]
+    struct Await {
+        rx: Receiver<()>,
+        done: Sender<()>,
+    }
+    impl Await {
+        fn wait(&mut self, i: WaitIn) -> WaitOut {
+            let WaitIn::N(N { n }) = i;
+            let signalled = self.rx.recv_timeout(Duration::from_secs(5)).ok().map(|_| n);
+            let _ = self.done.send(());
+            WaitOut::Signalled(Signalled { signalled })
+        }
+    }
+    #[derive(crate::Transition)]
+    #[ntpnet_transition(signal: SignalIn(N) -> SignalOut(Signalled))]
+    struct Signal {
+        tx: Sender<()>,
+    }
+    impl Signal {
+        fn signal(&mut self, i: SignalIn) -> SignalOut {
+            let SignalIn::N(N { n }) = i;
+            let _ = self.tx.send(());
+            SignalOut::Signalled(Signalled { signalled: Some(n) })
+        }
+    }
+
+    fn countdown() -> Net {
+        Net::make()
+            .set_start_tokens("N", vec![Token::new(3u32)])
+            .add_typed_transition("c", || Countdown {})
+            .place_to_transition("N", "n", "c")
+            .transition_to_place("c", "n", "N")
+            .transition_to_place("c", "done", "D")
+    }
+
+    #[test]
+    fn failed_clusters_end_without_places() {
+        let (exit_tx, _exit_rx) = unbounded();
+        let makers: Vec<WorkClusterMaker> = vec![
+            Box::new(|| work_cluster(countdown()).0),
+            Box::new(move || {
+                let (mut wc, exit) = work_cluster(countdown());
+                exit_tx.send(exit).unwrap();
+                wc.set_scheduling_policy(Box::new(Panicking {}));
+                wc
+            }),
+            Box::new(|| panic!("make")),
+        ];
+        let end_states = ThreadPool::make(makers, 2).run();
+        assert_eq!(end_states.len(), 3);
+        assert_eq!(tokens::<u32>(end_states[0].as_ref().unwrap(), "N"), vec![3]);
+        assert!(end_states[1].is_none());
+        assert!(end_states[2].is_none());
+    }
+
+    #[test]
+    fn idle_workers_take_any_ready_cluster() {
+        let (tx, rx) = unbounded();
+        let (done_tx, done_rx) = unbounded();
+        let (exits_tx, exits_rx) = unbounded();
+        let maker = |net: Net| -> WorkClusterMaker {
+            let exits_tx = exits_tx.clone();
+            Box::new(move || {
+                let (wc, exit) = work_cluster(net);
+                exits_tx.send(exit).unwrap();
+                wc
+            })
+        };
+        let signal = |net: Net| {
+            net.set_start_tokens("N", vec![Token::new(0u32)])
+                .place_to_transition("N", "n", "s")
+                .transition_to_place("s", "signalled", "S")
+        };
+        let makers = vec![
+            maker(signal(Net::make().add_typed_transition("s", move || {
+                Await { rx, done: done_tx }
+            }))),
+            maker(countdown()),
+            maker(signal(
+                Net::make().add_typed_transition("s", move || Signal { tx }),
+            )),
+        ];
+        let pool = thread::spawn(move || ThreadPool::make(makers, 2).run());
+        done_rx.recv().unwrap();
+        drop(exits_rx.iter().take(3).collect::<Vec<_>>());
+        let end_states = pool.join().unwrap();
+        assert_eq!(
+            tokens::<Option<u32>>(end_states[0].as_ref().unwrap(), "S"),
+            vec![Some(0)]
+        );
+    }
+}
@@ -0,0 +1,176 @@
+use crossbeam_channel::{Receiver, Sender};
+use serde_json::{json, Value};
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+#[derive(Debug, Clone)]
+pub(crate) struct Tracer {
+    tid: usize,
+    start: Instant,
+    tx: Sender<Value>,
+    flows: Arc<AtomicU64>,
+}
+impl Tracer {
+    pub fn make(tx: Sender<Value>) -> Self {
+        Self {
+            tid: 0,
+            start: Instant::now(),
+            tx,
+            flows: Arc::new(AtomicU64::new(0)),
+        }
+    }
+    pub fn thread(&self, tid: usize, name: &str) -> Self {
+        let tracer = Self {
+            tid,
+            ..self.clone()
+        };
+        tracer.emit(json!({
+            "name": "thread_name",
+            "ph": "M",
+            "pid": 0,
+            "tid": tid,
+            "args": { "name": name },
+        }));
+        tracer
+    }
+    fn ts(&self, at: Instant) -> f64 {
+        (at - self.start).as_secs_f64() * 1e6
+    }
+    fn emit(&self, event: Value) {
+        let _ = self.tx.send(event);
+    }
+    pub fn slice(&self, cat: &str, name: &str, begin: Instant, args: Value) {
+        let begin = self.ts(begin);
+        self.emit(json!({
+            "name": name,
+            "cat": cat,
+            "ph": "X",
+            "ts": begin,
+            "dur": self.ts(Instant::now()) - begin,
+            "pid": 0,
+            "tid": self.tid,
+            "args": args,
+        }));
+    }
+    fn flow(&self, ph: &str, id: u64) {
+        self.emit(json!({
+            "name": "token",
+            "cat": "token",
+            "ph": ph,
+            "id": id,
+            "ts": self.ts(Instant::now()),
+            "pid": 0,
+            "tid": self.tid,
+        }));
+    }
+    pub fn flow_start(&self) -> u64 {
+        let id = self.flows.fetch_add(1, Ordering::Relaxed);
+        self.flow("s", id);
+        id
+    }
+    pub fn flow_end(&self, id: u64) {
+        self.flow("f", id);
+    }
+}
+
+pub(crate) fn write_trace(path: &Path, rx: Receiver<Value>) -> io::Result<()> {
+    let events = rx.try_iter().collect::<Vec<_>>();
+    fs::write(
+        path,
+        serde_json::to_string(&json!({ "traceEvents": events }))?,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::countdown::Countdown;
+    use crate::testing::work_cluster;
+    use crate::{Net, Token};
+    use crossbeam_channel::unbounded;
+    use tempfile::NamedTempFile;
+
+    fn countdown() -> Net {
+        Net::make()
+            .set_start_tokens("N", vec![Token::new(3u32)])
+            .add_typed_transition("c", || Countdown {})
+            .place_to_transition("N", "n", "c")
+            .transition_to_place("c", "n", "N")
+            .transition_to_place("c", "done", "D")
+    }
+
+    #[test]
+    fn threads_share_flow_ids() {
+        let (tx, rx) = unbounded();
+        let tracer = Tracer::make(tx);
+        let a = tracer.thread(1, "work-cluster-0");
+        let b = tracer.thread(2, "work-cluster-1");
+        let id = a.flow_start();
+        b.flow_end(id);
+        assert_eq!(b.flow_start(), id + 1);
+        let events = rx.try_iter().collect::<Vec<_>>();
+        assert_eq!(events[0]["ph"], "M");
+        assert_eq!(events[0]["args"]["name"], "work-cluster-0");
+        assert_eq!(events[1]["tid"], 2);
+        assert_eq!(
+            events[2..]
+                .iter()
+                .map(|e| (e["ph"].as_str().unwrap(), e["tid"].as_u64().unwrap()))
+                .collect::<Vec<_>>(),
+            vec![("s", 1), ("f", 2), ("s", 2)]
+        );
+        assert_eq!(events[2]["id"], events[3]["id"]);
+    }
+
+    #[test]
+    fn slices_cover_their_duration() {
+        let (tx, rx) = unbounded();
+        let tracer = Tracer::make(tx).thread(3, "t");
+        let begin = Instant::now();
+        tracer.slice("transition", "c", begin, json!({ "case": "count" }));
+        let event = rx.try_iter().last().unwrap();
+        assert_eq!(event["ph"], "X");
+        assert_eq!(event["tid"], 3);
+        assert_eq!(event["args"]["case"], "count");
+        assert!(event["ts"].as_f64().unwrap() >= 0.0);
+        assert!(event["dur"].as_f64().unwrap() >= 0.0);
+    }
+
+    #[test]
+    fn work_clusters_trace_each_firing() {
+        let (tx, rx) = unbounded();
+        let (mut wc, _exit_tx) = work_cluster(countdown());
+        wc.trace(Tracer::make(tx).thread(1, "work-cluster-0"));
+        wc.begin();
+        wc.fire();
+        let file = NamedTempFile::new().unwrap();
+        write_trace(file.path(), rx).unwrap();
+        let trace: Value = serde_json::from_str(&fs::read_to_string(file.path()).unwrap()).unwrap();
+        let firings = trace["traceEvents"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter(|e| e["cat"] == "transition")
+            .map(|e| e["name"].as_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(firings, vec!["c"; 4]);
+    }
+
+    #[test]
+    fn idle_time_is_traced_as_blocked() {
+        let (tx, rx) = unbounded();
+        let (mut wc, _exit_tx) = work_cluster(countdown());
+        wc.trace(Tracer::make(tx).thread(1, "work-cluster-0"));
+        wc.begin();
+        wc.fire();
+        assert!(!rx.try_iter().any(|e| e["name"] == "blocked"));
+        wc.fire();
+        assert!(rx
+            .try_iter()
+            .any(|e| e["cat"] == "reactor" && e["name"] == "blocked"));
+    }
+}
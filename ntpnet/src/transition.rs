@@ -1,21 +1,65 @@
-use std::any::TypeId;
+use std::any::{type_name, TypeId};
 use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::pin::Pin;
 
-use crate::Token;
+use crate::{GuardView, Token};
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Description {
     pub in_edges: HashSet<(String, TypeId)>,
     pub out_edges: HashSet<(String, TypeId)>,
     pub cases: HashMap<String, Case>,
+    pub type_names: HashMap<TypeId, &'static str>,
+    pub in_arity: HashMap<(String, String), EdgeArity>,
+    pub out_arity: HashMap<(String, String), EdgeArity>,
 }
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EdgeArity {
+    Single,
+    Fixed(usize),
+    Any,
+}
+impl EdgeArity {
+    pub fn default_weight(&self) -> Option<usize> {
+        match self {
+            EdgeArity::Single => Some(1),
+            EdgeArity::Fixed(n) => Some(*n),
+            EdgeArity::Any => None,
+        }
+    }
+    pub fn accepts(&self, weight: usize) -> bool {
+        match self {
+            EdgeArity::Single => weight == 1,
+            EdgeArity::Fixed(n) => weight == *n,
+            EdgeArity::Any => weight > 0,
+        }
+    }
+}
+#[derive(Debug, Clone)]
 pub struct Case {
     pub inputs: Vec<HashSet<(String, TypeId)>>,
     pub outputs: Vec<HashSet<(String, TypeId)>>,
+    pub guarded: Vec<bool>,
+    pub priority: i32,
+    pub error: Option<String>,
+    pub asynchronous: bool,
 }
 
-pub trait Transition {
+pub type CaseFuture<'a> =
+    Pin<Box<dyn Future<Output = (HashMap<(String, TypeId), Token>, usize)> + Send + 'a>>;
+
+pub fn error_type<S: ?Sized, I, O, E: 'static>(
+    _case: fn(&mut S, I) -> std::result::Result<O, E>,
+) -> (TypeId, &'static str) {
+    (TypeId::of::<E>(), type_name::<E>())
+}
+
+pub trait Describe {
+    fn describe() -> Description;
+}
+
+pub trait Transition: Send {
     fn description(&self) -> Description;
     fn call(
         &mut self,
@@ -24,6 +68,17 @@ pub trait Transition {
         in_map: &mut HashMap<(String, TypeId), Token>,
         out_map: &mut HashMap<(String, TypeId), Token>,
     ) -> usize;
+    fn call_async<'a>(
+        &'a mut self,
+        case: &'a str,
+        _condition: usize,
+        _in_map: HashMap<(String, TypeId), Token>,
+    ) -> CaseFuture<'a> {
+        unimplemented!("case {} is not async", case)
+    }
+    fn guard(&self, _case: &str, _condition: usize, _view: &mut GuardView) -> bool {
+        true
+    }
 }
 
 use std::fmt::{Debug, Result};
@@ -32,3 +87,59 @@ impl Debug for dyn Transition {
         write!(f, "Transition{{{:#?}}}", self.description())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{run, tokens};
+    use crate::Net;
+    use std::num::ParseIntError;
+
+    #[derive(crate::TransitionInputTokensMacro)]
+    struct Text {
+        text: String,
+    }
+    #[derive(crate::TransitionOutputTokensMacro)]
+    struct Parsed {
+        parsed: u32,
+    }
+    #[derive(crate::Transition)]
+    #[ntpnet_transition(parse: Input(Text) -> Output(Parsed))]
+    #[ntpnet_error(parse: error)]
+    struct Parse {}
+    impl Parse {
+        fn parse(&mut self, i: Input) -> std::result::Result<Output, ParseIntError> {
+            let Input::Text(Text { text }) = i;
+            Ok(Output::Parsed(Parsed {
+                parsed: text.parse()?,
+            }))
+        }
+    }
+
+    #[test]
+    fn error_edges_are_described() {
+        let d = Parse::describe();
+        let error = ("error".to_string(), TypeId::of::<ParseIntError>());
+        assert!(d.out_edges.contains(&error));
+        assert_eq!(d.cases["parse"].error, Some("error".into()));
+        assert!(d.cases["parse"].outputs.iter().any(|o| o.contains(&error)));
+    }
+
+    #[test]
+    fn errors_are_routed_to_the_error_edge() {
+        let places = run(Net::make()
+            .set_start_tokens(
+                "T",
+                ["1", "x", "3"].map(|t| Token::new(t.to_string())).into(),
+            )
+            .add_typed_transition("p", || Parse {})
+            .place_to_transition("T", "text", "p")
+            .transition_to_place("p", "parsed", "P")
+            .transition_to_place("p", "error", "E"));
+        assert_eq!(tokens::<u32>(&places, "P"), vec![1, 3]);
+        assert_eq!(
+            tokens::<ParseIntError>(&places, "E"),
+            vec!["x".parse::<u32>().unwrap_err()]
+        );
+    }
+}
@@ -1,9 +1,11 @@
 use std::any::TypeId;
 use std::collections::{HashMap, HashSet};
 
-use crate::Token;
+use crate::{transition::EdgeArity, Token};
 
 pub trait TransitionInputTokens {
     fn from_map(in_map: &mut HashMap<(String, TypeId), Token>) -> Self;
     fn in_edges() -> HashSet<(String, TypeId)>;
+    fn type_names() -> HashMap<TypeId, &'static str>;
+    fn arity() -> HashMap<String, EdgeArity>;
 }
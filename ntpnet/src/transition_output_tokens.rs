@@ -1,9 +1,11 @@
 use std::any::TypeId;
 use std::collections::{HashMap, HashSet};
 
-use crate::Token;
+use crate::{transition::EdgeArity, Token};
 
 pub trait TransitionOutputTokens {
-    fn into_map(self: Self, map: &mut HashMap<(String, TypeId), Token>);
+    fn into_map(self, map: &mut HashMap<(String, TypeId), Token>);
     fn out_edges() -> HashSet<(String, TypeId)>;
+    fn type_names() -> HashMap<TypeId, &'static str>;
+    fn arity() -> HashMap<String, EdgeArity>;
 }
@@ -0,0 +1,857 @@
+use itertools::Itertools;
+use std::any::{type_name, TypeId};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt;
+use std::mem;
+use std::time::Instant;
+
+use crate::{
+    net::Net,
+    transition::{Description, EdgeArity},
+    FaultPolicy, TransitionFault,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NetError {
+    UnknownTransition {
+        transition: String,
+    },
+    UnknownInEdge {
+        transition: String,
+        edge: String,
+        place: String,
+    },
+    UnknownOutEdge {
+        transition: String,
+        edge: String,
+        place: String,
+    },
+    UnwiredInEdge {
+        transition: String,
+        edge: String,
+    },
+    UnwiredOutEdge {
+        transition: String,
+        edge: String,
+    },
+    DuplicateEdge {
+        transition: String,
+        edge: String,
+        places: Vec<String>,
+    },
+    IsolatedPlace {
+        place: String,
+    },
+    PlaceWithoutProducer {
+        place: String,
+    },
+    TransitionNotInWorkCluster {
+        transition: String,
+    },
+    TransitionInMultipleWorkClusters {
+        transition: String,
+        clusters: Vec<usize>,
+    },
+    UnknownWorkClusterTransition {
+        transition: String,
+        cluster: usize,
+    },
+    PlaceConsumedByMultipleWorkClusters {
+        place: String,
+        clusters: Vec<usize>,
+    },
+    TypeMismatch {
+        place: String,
+        transition: String,
+        edge: String,
+        consumed: Vec<String>,
+        produced: Vec<String>,
+    },
+    InWeightMismatch {
+        place: String,
+        transition: String,
+        edge: String,
+        weight: Option<usize>,
+        arity: EdgeArity,
+    },
+    OutWeightMismatch {
+        transition: String,
+        place: String,
+        edge: String,
+        weight: Option<usize>,
+        arity: EdgeArity,
+    },
+    EmbedCollision {
+        prefix: String,
+        name: String,
+    },
+    AsyncFaultPolicy {
+        transition: String,
+        case: String,
+        policy: String,
+    },
+}
+
+fn arity_str(arity: &EdgeArity) -> String {
+    match arity {
+        EdgeArity::Single => "exactly one token".into(),
+        EdgeArity::Fixed(n) => format!("exactly {} tokens", n),
+        EdgeArity::Any => "a weighted number of tokens".into(),
+    }
+}
+
+fn weight_str(weight: &Option<usize>) -> String {
+    match weight {
+        Some(w) => format!("weight {}", w),
+        None => "no weight".into(),
+    }
+}
+
+impl fmt::Display for NetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NetError::UnknownTransition { transition } => {
+                write!(f, "transition '{}' is wired but never added", transition)
+            }
+            NetError::UnknownInEdge {
+                transition,
+                edge,
+                place,
+            } => write!(
+                f,
+                "{} -> {}: '{}' is not an input edge of '{}'",
+                place, transition, edge, transition
+            ),
+            NetError::UnknownOutEdge {
+                transition,
+                edge,
+                place,
+            } => write!(
+                f,
+                "{} -> {}: '{}' is not an output edge of '{}'",
+                transition, place, edge, transition
+            ),
+            NetError::UnwiredInEdge { transition, edge } => write!(
+                f,
+                "input edge '{}' of '{}' is not connected to any place",
+                edge, transition
+            ),
+            NetError::UnwiredOutEdge { transition, edge } => write!(
+                f,
+                "output edge '{}' of '{}' is not connected to any place",
+                edge, transition
+            ),
+            NetError::DuplicateEdge {
+                transition,
+                edge,
+                places,
+            } => write!(
+                f,
+                "edge '{}' of '{}' is connected to several places: {:?}",
+                edge, transition, places
+            ),
+            NetError::IsolatedPlace { place } => {
+                write!(f, "place '{}' has no producers and no consumers", place)
+            }
+            NetError::PlaceWithoutProducer { place } => write!(
+                f,
+                "place '{}' is consumed but has no producers and no start tokens",
+                place
+            ),
+            NetError::TransitionNotInWorkCluster { transition } => write!(
+                f,
+                "transition '{}' is not present in any work cluster",
+                transition
+            ),
+            NetError::TransitionInMultipleWorkClusters {
+                transition,
+                clusters,
+            } => write!(
+                f,
+                "transition '{}' is present in several work clusters: {:?}",
+                transition, clusters
+            ),
+            NetError::UnknownWorkClusterTransition {
+                transition,
+                cluster,
+            } => write!(
+                f,
+                "work cluster {} contains unknown transition '{}'",
+                cluster, transition
+            ),
+            NetError::PlaceConsumedByMultipleWorkClusters { place, clusters } => write!(
+                f,
+                "place '{}' is consumed or inhibited by several work clusters: {:?}",
+                place, clusters
+            ),
+            NetError::TypeMismatch {
+                place,
+                transition,
+                edge,
+                consumed,
+                produced,
+            } => write!(
+                f,
+                "{} -> {}: edge '{}' consumes {:?} but place '{}' only receives {:?}",
+                place, transition, edge, consumed, place, produced
+            ),
+            NetError::InWeightMismatch {
+                place,
+                transition,
+                edge,
+                weight,
+                arity,
+            } => write!(
+                f,
+                "{} -> {}: edge '{}' takes {} but is wired with {}",
+                place,
+                transition,
+                edge,
+                arity_str(arity),
+                weight_str(weight)
+            ),
+            NetError::OutWeightMismatch {
+                transition,
+                place,
+                edge,
+                weight,
+                arity,
+            } => write!(
+                f,
+                "{} -> {}: edge '{}' produces {} but is wired with {}",
+                transition,
+                place,
+                edge,
+                arity_str(arity),
+                weight_str(weight)
+            ),
+            NetError::EmbedCollision { prefix, name } => write!(
+                f,
+                "embedding '{}' would overwrite '{}' in the parent net",
+                prefix, name
+            ),
+            NetError::AsyncFaultPolicy {
+                transition,
+                case,
+                policy,
+            } => write!(
+                f,
+                "async case '{}' of '{}' can not recover from a panic with fault policy '{}'",
+                case, transition, policy
+            ),
+        }
+    }
+}
+
+impl std::error::Error for NetError {}
+
+impl Net {
+    pub fn describe(&mut self) {
+        let names = self
+            .transitions
+            .keys()
+            .filter(|name| !self.descriptions.contains_key(*name))
+            .cloned()
+            .collect::<Vec<_>>();
+        for name in names {
+            let maker = self.transitions.get_mut(&name).unwrap();
+            let t = mem::replace(maker, Box::new(|| unreachable!()))();
+            self.descriptions.insert(name, t.description());
+            *maker = Box::new(move || t);
+        }
+    }
+    pub fn validate(&mut self) -> Result<(), Vec<NetError>> {
+        self.describe();
+        let mut errors = vec![];
+        let mut unknown_transitions = BTreeSet::new();
+        let mut in_wiring: BTreeMap<&String, BTreeMap<&String, Vec<String>>> = BTreeMap::new();
+        let mut out_wiring: BTreeMap<&String, BTreeMap<&String, Vec<String>>> = BTreeMap::new();
+        for ((p, t), e) in &self.pt_edges {
+            in_wiring
+                .entry(t)
+                .or_default()
+                .entry(e)
+                .or_default()
+                .push(p.clone());
+        }
+        for ((t, p), e) in &self.tp_edges {
+            out_wiring
+                .entry(t)
+                .or_default()
+                .entry(e)
+                .or_default()
+                .push(p.clone());
+        }
+        for t in in_wiring
+            .keys()
+            .chain(out_wiring.keys())
+            .cloned()
+            .chain(self.inhibitors.iter().map(|(_, t)| t))
+            .chain(self.fault_policies.keys())
+        {
+            if !self.transitions.contains_key(t) {
+                unknown_transitions.insert(t.clone());
+            }
+        }
+        for transition in unknown_transitions {
+            errors.push(NetError::UnknownTransition { transition });
+        }
+        for (t, d) in self.descriptions.iter().sorted_by_key(|x| x.0) {
+            let in_names = d.in_edges.iter().map(|(e, _)| e).collect::<BTreeSet<_>>();
+            let out_names = d.out_edges.iter().map(|(e, _)| e).collect::<BTreeSet<_>>();
+            let wired_in = in_wiring.remove(t).unwrap_or_default();
+            let wired_out = out_wiring.remove(t).unwrap_or_default();
+            for (e, mut places) in wired_in.into_iter() {
+                places.sort();
+                if !in_names.contains(e) {
+                    for place in places.iter() {
+                        errors.push(NetError::UnknownInEdge {
+                            transition: t.clone(),
+                            edge: e.clone(),
+                            place: place.clone(),
+                        });
+                    }
+                } else if places.len() > 1 {
+                    errors.push(NetError::DuplicateEdge {
+                        transition: t.clone(),
+                        edge: e.clone(),
+                        places,
+                    });
+                }
+            }
+            for (e, mut places) in wired_out.into_iter() {
+                places.sort();
+                if !out_names.contains(e) {
+                    for place in places.iter() {
+                        errors.push(NetError::UnknownOutEdge {
+                            transition: t.clone(),
+                            edge: e.clone(),
+                            place: place.clone(),
+                        });
+                    }
+                } else if places.len() > 1 {
+                    errors.push(NetError::DuplicateEdge {
+                        transition: t.clone(),
+                        edge: e.clone(),
+                        places,
+                    });
+                }
+            }
+            for e in in_names {
+                if !self.pt_edges.iter().any(|((_, t2), e2)| t2 == t && e2 == e) {
+                    errors.push(NetError::UnwiredInEdge {
+                        transition: t.clone(),
+                        edge: e.clone(),
+                    });
+                }
+            }
+            for e in out_names {
+                if !self.tp_edges.iter().any(|((t2, _), e2)| t2 == t && e2 == e) {
+                    errors.push(NetError::UnwiredOutEdge {
+                        transition: t.clone(),
+                        edge: e.clone(),
+                    });
+                }
+            }
+        }
+        let fault_routes = self.fault_routes();
+        let produced = self
+            .tp_edges
+            .keys()
+            .map(|(_, p)| p)
+            .chain(fault_routes.keys())
+            .collect::<HashSet<_>>();
+        let inhibited = self
+            .inhibitors
+            .iter()
+            .map(|(p, _)| p)
+            .collect::<HashSet<_>>();
+        for p in self.places.keys().sorted() {
+            let consumed = self
+                .place_to_transitions
+                .get(p)
+                .is_some_and(|ts| !ts.is_empty());
+            let has_tokens = self.places[p].values().any(|q| !q.is_empty());
+            if produced.contains(p)
+                || self.timers.contains_key(p)
+                || self.external_places.contains(p)
+            {
+                continue;
+            }
+            if !consumed && !inhibited.contains(p) {
+                errors.push(NetError::IsolatedPlace { place: p.clone() });
+            } else if consumed && !has_tokens {
+                errors.push(NetError::PlaceWithoutProducer { place: p.clone() });
+            }
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+    pub fn type_check(&self) -> Result<(), Vec<NetError>> {
+        let mut errors = vec![];
+        let fault_routes = self.fault_routes();
+        let mut produced: HashMap<&String, BTreeMap<TypeId, &'static str>> = HashMap::new();
+        let mut unknown = HashSet::new();
+        for ((t, p), e) in &self.tp_edges {
+            match self.descriptions.get(t) {
+                Some(d) => {
+                    for (_, ty) in d.out_edges.iter().filter(|(e2, _)| e2 == e) {
+                        produced
+                            .entry(p)
+                            .or_default()
+                            .insert(*ty, d.type_names.get(ty).cloned().unwrap_or("?"));
+                    }
+                }
+                None => {
+                    unknown.insert(p);
+                }
+            }
+        }
+        for p in self.timers.keys() {
+            produced
+                .entry(p)
+                .or_default()
+                .insert(TypeId::of::<Instant>(), type_name::<Instant>());
+        }
+        for p in fault_routes.keys() {
+            produced.entry(p).or_default().insert(
+                TypeId::of::<TransitionFault>(),
+                type_name::<TransitionFault>(),
+            );
+        }
+        for (p, token_qs) in &self.places {
+            for (ty, q) in token_qs {
+                if let Some(t) = q.front() {
+                    produced
+                        .entry(p)
+                        .or_default()
+                        .insert(*ty, (**t).type_name());
+                }
+            }
+        }
+        for ((p, t), e) in self.pt_edges.iter().sorted() {
+            let d = match self.descriptions.get(t) {
+                Some(d) => d,
+                None => continue,
+            };
+            let consumed = d
+                .in_edges
+                .iter()
+                .filter(|(e2, _)| e2 == e)
+                .map(|(_, ty)| (*ty, d.type_names.get(ty).cloned().unwrap_or("?")))
+                .collect::<BTreeMap<_, _>>();
+            let received = match produced.get(p) {
+                Some(received) if !unknown.contains(p) => received,
+                _ => continue,
+            };
+            if !consumed.is_empty() && !consumed.keys().any(|ty| received.contains_key(ty)) {
+                errors.push(NetError::TypeMismatch {
+                    place: p.clone(),
+                    transition: t.clone(),
+                    edge: e.clone(),
+                    consumed: consumed.values().map(|n| n.to_string()).sorted().collect(),
+                    produced: received.values().map(|n| n.to_string()).sorted().collect(),
+                });
+            }
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+    fn edge_arities(
+        &self,
+        t: &str,
+        e: &str,
+        arities: fn(&Description) -> &HashMap<(String, String), EdgeArity>,
+    ) -> Vec<EdgeArity> {
+        match self.descriptions.get(t) {
+            Some(d) => arities(d)
+                .iter()
+                .filter(|((_, e2), _)| e2 == e)
+                .sorted_by_key(|(case_edge, _)| *case_edge)
+                .map(|(_, arity)| *arity)
+                .unique()
+                .collect(),
+            None => vec![],
+        }
+    }
+    pub fn check_weights(&self) -> Result<(), Vec<NetError>> {
+        let mut errors = vec![];
+        for ((p, t), e) in self.pt_edges.iter().sorted() {
+            let weight = self.pt_weights.get(&(p.clone(), t.clone())).cloned();
+            for arity in self.edge_arities(t, e, |d| &d.in_arity) {
+                if !weight
+                    .or(arity.default_weight())
+                    .is_some_and(|w| arity.accepts(w))
+                {
+                    errors.push(NetError::InWeightMismatch {
+                        place: p.clone(),
+                        transition: t.clone(),
+                        edge: e.clone(),
+                        weight,
+                        arity,
+                    });
+                }
+            }
+        }
+        for ((t, p), e) in self.tp_edges.iter().sorted() {
+            let weight = self.tp_weights.get(&(t.clone(), p.clone())).cloned();
+            for arity in self.edge_arities(t, e, |d| &d.out_arity) {
+                if !weight.is_none_or(|w| arity.accepts(w)) {
+                    errors.push(NetError::OutWeightMismatch {
+                        transition: t.clone(),
+                        place: p.clone(),
+                        edge: e.clone(),
+                        weight,
+                        arity,
+                    });
+                }
+            }
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+    pub fn check_fault_policies(&self) -> Result<(), Vec<NetError>> {
+        let mut errors = vec![];
+        for (t, policy) in self.fault_policies.iter().sorted_by_key(|(t, _)| *t) {
+            if !matches!(policy, FaultPolicy::DropTokens | FaultPolicy::RouteTo(_)) {
+                continue;
+            }
+            if let Some(d) = self.descriptions.get(t) {
+                for case in d
+                    .cases
+                    .iter()
+                    .filter(|(_, c)| c.asynchronous)
+                    .map(|(case, _)| case)
+                    .sorted()
+                {
+                    errors.push(NetError::AsyncFaultPolicy {
+                        transition: t.clone(),
+                        case: case.clone(),
+                        policy: policy.label(),
+                    });
+                }
+            }
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+    pub fn validate_work_clusters(
+        &self,
+        work_clusters: &[HashSet<String>],
+    ) -> Result<(), Vec<NetError>> {
+        let mut errors = vec![];
+        for (i, cluster) in work_clusters.iter().enumerate() {
+            for t in cluster.iter().sorted() {
+                if !self.transitions.contains_key(t) {
+                    errors.push(NetError::UnknownWorkClusterTransition {
+                        transition: t.clone(),
+                        cluster: i,
+                    });
+                }
+            }
+        }
+        for t in self.transitions.keys().sorted() {
+            let clusters = work_clusters
+                .iter()
+                .enumerate()
+                .filter_map(|(i, ts)| if ts.contains(t) { Some(i) } else { None })
+                .collect::<Vec<_>>();
+            if clusters.is_empty() {
+                errors.push(NetError::TransitionNotInWorkCluster {
+                    transition: t.clone(),
+                });
+            } else if clusters.len() > 1 {
+                errors.push(NetError::TransitionInMultipleWorkClusters {
+                    transition: t.clone(),
+                    clusters,
+                });
+            }
+        }
+        for (p, ts) in self.place_readers().iter().sorted_by_key(|x| x.0) {
+            let clusters = work_clusters
+                .iter()
+                .enumerate()
+                .filter_map(|(i, cluster)| {
+                    if ts.iter().any(|t| cluster.contains(t)) {
+                        Some(i)
+                    } else {
+                        None
+                    }
+                })
+                .collect::<Vec<_>>();
+            if clusters.len() > 1 {
+                errors.push(NetError::PlaceConsumedByMultipleWorkClusters {
+                    place: p.clone(),
+                    clusters,
+                });
+            }
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::batch::Batch;
+    use crate::testing::countdown::{Countdown, N};
+    use crate::transition::Describe;
+    use crate::Token;
+
+    #[derive(crate::TransitionInputTokensMacro)]
+    struct Text {
+        text: String,
+    }
+    #[derive(crate::TransitionOutputTokensMacro)]
+    struct Echoed {
+        echoed: String,
+    }
+    #[derive(crate::Transition)]
+    #[ntpnet_transition(echo: EchoIn(Text) -> EchoOut(Echoed))]
+    struct Echo {}
+    impl Echo {
+        fn echo(&mut self, i: EchoIn) -> EchoOut {
+            let EchoIn::Text(Text { text }) = i;
+            EchoOut::Echoed(Echoed { echoed: text })
+        }
+    }
+
+    #[derive(crate::TransitionOutputTokensMacro)]
+    struct Checked {
+        checked: u32,
+    }
+    #[derive(crate::Transition)]
+    #[ntpnet_transition(lookup: LookupIn(N) -> LookupOut(Checked))]
+    #[ntpnet_async(lookup)]
+    struct Lookup {}
+    impl Lookup {
+        async fn lookup(&mut self, i: LookupIn) -> LookupOut {
+            let LookupIn::N(N { n }) = i;
+            LookupOut::Checked(Checked { checked: n })
+        }
+    }
+
+    fn countdown() -> Net {
+        Net::make()
+            .set_start_tokens("N", vec![Token::new(3u32)])
+            .add_typed_transition("c", || Countdown {})
+            .place_to_transition("N", "n", "c")
+            .transition_to_place("c", "n", "N")
+    }
+
+    #[test]
+    fn validate_reports_wiring_errors() {
+        let errors = countdown()
+            .transition_to_place("c", "nope", "X")
+            .place_to_transition("N", "n", "ghost")
+            .validate()
+            .unwrap_err();
+        assert!(errors.contains(&NetError::UnknownTransition {
+            transition: "ghost".into()
+        }));
+        assert!(errors.contains(&NetError::UnknownOutEdge {
+            transition: "c".into(),
+            edge: "nope".into(),
+            place: "X".into(),
+        }));
+        assert!(errors.contains(&NetError::UnwiredOutEdge {
+            transition: "c".into(),
+            edge: "done".into(),
+        }));
+    }
+
+    #[test]
+    fn validate_accepts_wired_net() {
+        assert_eq!(
+            countdown().transition_to_place("c", "done", "D").validate(),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn validate_reports_isolated_place() {
+        let errors = countdown()
+            .transition_to_place("c", "done", "D")
+            .set_start_tokens("I", vec![Token::new(0u32)])
+            .validate()
+            .unwrap_err();
+        assert_eq!(errors, vec![NetError::IsolatedPlace { place: "I".into() }]);
+    }
+
+    #[test]
+    fn validate_accepts_external_places() {
+        let net = || {
+            Net::make()
+                .add_typed_transition("c", || Countdown {})
+                .place_to_transition("In", "n", "c")
+                .transition_to_place("c", "n", "Out")
+                .transition_to_place("c", "done", "D")
+        };
+        assert_eq!(
+            net().validate(),
+            Err(vec![NetError::PlaceWithoutProducer { place: "In".into() }])
+        );
+        assert_eq!(net().add_external_place("In").validate(), Ok(()));
+    }
+
+    #[test]
+    fn validate_describes_untyped_transitions() {
+        let mut net = Net::make()
+            .set_start_tokens("N", vec![Token::new(3u32)])
+            .add_transition("c", Box::new(|| Box::new(Countdown {})))
+            .place_to_transition("N", "n", "c")
+            .transition_to_place("c", "n", "N");
+        assert_eq!(
+            net.validate(),
+            Err(vec![NetError::UnwiredOutEdge {
+                transition: "c".into(),
+                edge: "done".into(),
+            }])
+        );
+        assert!(net.descriptions.contains_key("c"));
+        assert_eq!(net.transition_to_place("c", "done", "D").validate(), Ok(()));
+    }
+
+    #[test]
+    fn validate_work_clusters_reports_bad_partitions() {
+        let net = countdown()
+            .transition_to_place("c", "done", "D")
+            .add_typed_transition("d", || Countdown {})
+            .place_to_transition("N", "n", "d")
+            .transition_to_place("d", "n", "N")
+            .transition_to_place("d", "done", "D");
+        let set = |ts: &[&str]| ts.iter().map(|t| t.to_string()).collect::<HashSet<_>>();
+        assert_eq!(net.validate_work_clusters(&[set(&["c", "d"])]), Ok(()));
+        let errors = net
+            .validate_work_clusters(&[set(&["c", "ghost"]), set(&["d"]), set(&["d"])])
+            .unwrap_err();
+        assert_eq!(
+            errors,
+            vec![
+                NetError::UnknownWorkClusterTransition {
+                    transition: "ghost".into(),
+                    cluster: 0,
+                },
+                NetError::TransitionInMultipleWorkClusters {
+                    transition: "d".into(),
+                    clusters: vec![1, 2],
+                },
+                NetError::PlaceConsumedByMultipleWorkClusters {
+                    place: "N".into(),
+                    clusters: vec![0, 1, 2],
+                },
+            ]
+        );
+        let errors = net.validate_work_clusters(&[set(&["c"])]).unwrap_err();
+        assert!(errors.contains(&NetError::TransitionNotInWorkCluster {
+            transition: "d".into()
+        }));
+    }
+
+    #[test]
+    fn type_check_reports_mismatched_arcs() {
+        let net = countdown()
+            .add_typed_transition("e", || Echo {})
+            .transition_to_place("c", "done", "D")
+            .place_to_transition("D", "text", "e")
+            .transition_to_place("e", "echoed", "E");
+        assert_eq!(
+            net.type_check(),
+            Err(vec![NetError::TypeMismatch {
+                place: "D".into(),
+                transition: "e".into(),
+                edge: "text".into(),
+                consumed: vec![type_name::<String>().into()],
+                produced: vec![type_name::<u32>().into()],
+            }])
+        );
+    }
+
+    #[test]
+    fn type_check_skips_places_with_undescribed_producers() {
+        let net = countdown()
+            .add_typed_transition("e", || Echo {})
+            .add_transition("s", Box::new(|| Box::new(Echo {})))
+            .transition_to_place("c", "done", "D")
+            .transition_to_place("s", "echoed", "D")
+            .place_to_transition("D", "text", "e")
+            .transition_to_place("e", "echoed", "E");
+        assert_eq!(net.type_check(), Ok(()));
+    }
+
+    #[test]
+    fn type_check_does_not_build_typed_transitions() {
+        let net = Net::make()
+            .set_start_tokens("N", vec![Token::new(3u32)])
+            .add_typed_transition("c", || -> Countdown {
+                panic!("transition built during type check")
+            })
+            .place_to_transition("N", "n", "c")
+            .transition_to_place("c", "n", "N")
+            .transition_to_place("c", "done", "D");
+        assert_eq!(net.type_check(), Ok(()));
+    }
+
+    #[test]
+    fn check_weights_uses_the_arity_of_each_case() {
+        let d = Batch::describe();
+        assert_eq!(
+            d.in_arity[&("pair".into(), "n".into())],
+            EdgeArity::Fixed(2)
+        );
+        assert_eq!(d.in_arity[&("one".into(), "n".into())], EdgeArity::Single);
+        let net = Net::make()
+            .add_typed_transition("b", || Batch {})
+            .place_to_transition_weighted("N", "n", "b", 2)
+            .transition_to_place("b", "total", "T");
+        assert_eq!(
+            net.check_weights(),
+            Err(vec![NetError::InWeightMismatch {
+                place: "N".into(),
+                transition: "b".into(),
+                edge: "n".into(),
+                weight: Some(2),
+                arity: EdgeArity::Single,
+            }])
+        );
+    }
+
+    #[test]
+    fn check_fault_policies_rejects_lossy_policies_on_async_cases() {
+        let net = |policy| {
+            Net::make()
+                .add_typed_transition("l", || Lookup {})
+                .place_to_transition("N", "n", "l")
+                .transition_to_place("l", "checked", "C")
+                .set_fault_policy("l", policy)
+        };
+        assert_eq!(
+            net(FaultPolicy::DropTokens).check_fault_policies(),
+            Err(vec![NetError::AsyncFaultPolicy {
+                transition: "l".into(),
+                case: "lookup".into(),
+                policy: "drop tokens".into(),
+            }])
+        );
+        assert!(net(FaultPolicy::RouteTo("F".into()))
+            .check_fault_policies()
+            .is_err());
+        assert_eq!(net(FaultPolicy::Shutdown).check_fault_policies(), Ok(()));
+    }
+}
@@ -1,42 +1,187 @@
 use bimap::BiMap;
 use crossbeam_channel::{Receiver, Sender};
+use itertools::Itertools;
+use std::any::Any;
 use std::any::TypeId;
 use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::future::Future;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Wake, Waker};
 
 use plotmux::plotsink::PlotSink;
+use serde_json::{json, Value};
 
-use crate::transition::{Description, Transition};
+use crate::transition::{Description, EdgeArity, Transition};
 use crate::{
+    capacity::{Bound, Slots},
     net::Net,
+    observer::Observers,
+    pseudo_state_monitor::NonblockingState,
+    replay::{Replay, Turn},
     state::{State, StateBlockable, StateDelta},
-    PlotOptions, Token,
+    trace::Tracer,
+    Candidate, FaultPolicy, Firing, FirstMatch, GuardView, NetError, SchedulingPolicy, Token,
+    TransitionFault,
 };
 
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 #[derive(Debug)]
 struct TransitionRuntime {
-    t: Box<dyn Transition>,
+    t: Option<Box<dyn Transition>>,
     description: Description,
     in_edge_to_place: BiMap<String, String>,
     out_edge_to_place: BiMap<String, String>,
+    in_weights: HashMap<(String, String), usize>,
+    out_weights: HashMap<String, usize>,
+    inhibitors: HashSet<String>,
+    priority: i32,
+    delay: Option<Duration>,
+    fault_policy: FaultPolicy,
+}
+impl TransitionRuntime {
+    fn in_weight(&self, f_name: &str, place: &str) -> usize {
+        let e_name = self.in_edge_to_place.get_by_right(place).unwrap();
+        self.in_weights[&(f_name.to_string(), e_name.clone())]
+    }
+    fn enabled(&self, f_name: &str, condition: &HashSet<(String, TypeId)>, state: &State) -> bool {
+        condition
+            .iter()
+            .all(|p_ty| state.count(p_ty) >= self.in_weight(f_name, &p_ty.0))
+            && self.inhibitors.iter().all(|p| state.empty(p))
+            && self
+                .out_edge_to_place
+                .right_values()
+                .all(|p| state.has_room(p, *self.out_weights.get(p).unwrap_or(&1)))
+    }
+    fn push_outputs(
+        &self,
+        f_name: &str,
+        out_map: HashMap<(String, TypeId), Token>,
+        state: &mut State,
+    ) -> Result<(), String> {
+        let release = self.delay.map(|delay| Instant::now() + delay);
+        let stamp = |t: Token| match release {
+            Some(release) => t.with_release(release),
+            None => t,
+        };
+        let mut outputs = vec![];
+        for ((e_name, ty), t) in out_map.into_iter() {
+            let place = self.out_edge_to_place.get_by_left(&e_name).unwrap().clone();
+            let arity = self
+                .description
+                .out_arity
+                .get(&(f_name.into(), e_name.clone()));
+            let tokens = match arity {
+                Some(EdgeArity::Single) | None => vec![t],
+                Some(_) => {
+                    let tokens = *t.downcast::<Vec<Token>>().unwrap();
+                    match self.out_weights.get(&place) {
+                        Some(w) if tokens.len() != *w => {
+                            return Err(format!(
+                                "edge {} produced {} tokens for weight {}",
+                                e_name,
+                                tokens.len(),
+                                w
+                            ))
+                        }
+                        _ => tokens,
+                    }
+                }
+            };
+            outputs.push(((place, ty), tokens));
+        }
+        for (p_ty, tokens) in outputs {
+            for t in tokens {
+                state.push(&p_ty, stamp(t));
+            }
+        }
+        Ok(())
+    }
+}
+
+type AsyncOutput = (Box<dyn Transition>, HashMap<(String, TypeId), Token>, usize);
+struct AsyncCall {
+    t_name: String,
+    f_name: String,
+    launched: Instant,
+    future: Pin<Box<dyn Future<Output = AsyncOutput> + Send>>,
+}
+impl fmt::Debug for AsyncCall {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "AsyncCall({}: {})", self.t_name, self.f_name)
+    }
+}
+
+struct ClusterWaker {
+    id: usize,
+    tx: Sender<StateBlockable>,
+}
+impl Wake for ClusterWaker {
+    fn wake(self: Arc<Self>) {
+        let _ = self.tx.send(StateBlockable::Wake(self.id));
+    }
 }
 
 #[derive(Debug)]
 pub struct WorkCluster {
     transitions: HashMap<String, TransitionRuntime>,
     state: State,
-    plot_sink: PlotSink,
+    plot_sink: Arc<Mutex<PlotSink>>,
+    policy: Box<dyn SchedulingPolicy>,
+    async_calls: HashMap<usize, AsyncCall>,
+    next_async_call: usize,
+    recorder: Option<(usize, Sender<Firing>)>,
+    replay: Option<(usize, Arc<Replay>)>,
+    tracer: Option<Tracer>,
+    observers: (usize, Observers),
+    start: Instant,
+    blocked_since: Option<Instant>,
 }
 impl WorkCluster {
+    #[allow(clippy::too_many_arguments)]
     pub fn make(
         n: Net,
         input_places: HashMap<String, Receiver<StateBlockable>>,
         output_places: HashMap<String, Sender<StateBlockable>>,
-        plot_sink: PlotSink,
+        slots: HashMap<String, Slots>,
+        plot_sink: Arc<Mutex<PlotSink>>,
         state_delta_notification: Sender<StateDelta>,
+        wake: (Sender<StateBlockable>, Receiver<StateBlockable>),
         exit_rx: Receiver<StateBlockable>,
     ) -> Self {
+        Self::try_make(
+            n,
+            input_places,
+            output_places,
+            slots,
+            plot_sink,
+            state_delta_notification,
+            wake,
+            exit_rx,
+        )
+        .unwrap_or_else(|errors| {
+            panic!(
+                "invalid work cluster:\n{}",
+                errors.iter().map(|e| e.to_string()).join("\n")
+            )
+        })
+    }
+    #[allow(clippy::too_many_arguments)]
+    pub fn try_make(
+        mut n: Net,
+        input_places: HashMap<String, Receiver<StateBlockable>>,
+        output_places: HashMap<String, Sender<StateBlockable>>,
+        mut slots: HashMap<String, Slots>,
+        plot_sink: Arc<Mutex<PlotSink>>,
+        state_delta_notification: Sender<StateDelta>,
+        wake: (Sender<StateBlockable>, Receiver<StateBlockable>),
+        exit_rx: Receiver<StateBlockable>,
+    ) -> Result<Self, Vec<NetError>> {
+        let mut errors = vec![];
         let transitions = n
             .transitions
             .into_iter()
@@ -55,179 +200,734 @@ impl WorkCluster {
                     .filter(|((t, _), _)| t == &name)
                     .map(|((_, p), e)| (e.clone(), p.clone()))
                     .collect::<BiMap<String, String>>();
+                let in_weights = d
+                    .cases
+                    .iter()
+                    .flat_map(|(f_name, case)| {
+                        case.inputs
+                            .iter()
+                            .flatten()
+                            .map(move |(e, _)| (f_name.clone(), e.clone()))
+                    })
+                    .unique()
+                    .filter_map(|(f_name, e)| {
+                        let p = in_edge_to_place.get_by_left(&e)?;
+                        let arity = d
+                            .in_arity
+                            .get(&(f_name.clone(), e.clone()))
+                            .cloned()
+                            .unwrap_or(EdgeArity::Single);
+                        let weight = n
+                            .pt_weights
+                            .get(&(p.clone(), name.clone()))
+                            .cloned()
+                            .or(arity.default_weight());
+                        let error = NetError::InWeightMismatch {
+                            place: p.clone(),
+                            transition: name.clone(),
+                            edge: e.clone(),
+                            weight,
+                            arity,
+                        };
+                        if weight.is_none() && !errors.contains(&error) {
+                            errors.push(error);
+                        }
+                        weight.map(|w| ((f_name, e), w))
+                    })
+                    .collect::<HashMap<_, _>>();
+                let out_weights = out_edge_to_place
+                    .iter()
+                    .filter_map(|(_, p)| {
+                        n.tp_weights
+                            .get(&(name.clone(), p.clone()))
+                            .map(|w| (p.clone(), *w))
+                    })
+                    .collect::<HashMap<_, _>>();
+                let priority = *n.priorities.get(&name).unwrap_or(&0);
+                let delay = n.delays.get(&name).cloned();
+                let fault_policy = n
+                    .fault_policies
+                    .remove(&name)
+                    .unwrap_or(FaultPolicy::Shutdown);
+                let inhibitors = n
+                    .inhibitors
+                    .iter()
+                    .filter(|(_, t)| t == &name)
+                    .map(|(p, _)| p.clone())
+                    .collect::<HashSet<_>>();
+                let mut unwired_in = BTreeSet::new();
+                let mut unwired_out = BTreeSet::new();
                 for (_, case) in d.cases.iter_mut() {
                     for condition in case.inputs.iter_mut() {
                         *condition = condition
                             .iter()
-                            .map(|(edge, ty)| {
-                                (
-                                    in_edge_to_place
-                                        .get_by_left(edge)
-                                        .expect(&format!(
-                                            "{}: {} not found on left of {:#?}",
-                                            name, edge, in_edge_to_place
-                                        ))
-                                        .clone(),
-                                    ty.clone(),
-                                )
+                            .filter_map(|(edge, ty)| match in_edge_to_place.get_by_left(edge) {
+                                Some(p) => Some((p.clone(), *ty)),
+                                None => {
+                                    unwired_in.insert(edge.clone());
+                                    None
+                                }
                             })
                             .collect::<HashSet<_>>();
                     }
                     for product in case.outputs.iter_mut() {
                         *product = product
                             .iter()
-                            .map(|(edge, ty)| {
-                                (
-                                    out_edge_to_place
-                                        .get_by_left(edge)
-                                        .expect(&format!(
-                                            "{}: {} not found on left of {:#?}",
-                                            name, edge, out_edge_to_place
-                                        ))
-                                        .clone(),
-                                    ty.clone(),
-                                )
+                            .filter_map(|(edge, ty)| match out_edge_to_place.get_by_left(edge) {
+                                Some(p) => Some((p.clone(), *ty)),
+                                None => {
+                                    unwired_out.insert(edge.clone());
+                                    None
+                                }
                             })
                             .collect::<_>();
                     }
                 }
+                errors.extend(unwired_in.into_iter().map(|edge| NetError::UnwiredInEdge {
+                    transition: name.clone(),
+                    edge,
+                }));
+                errors.extend(
+                    unwired_out
+                        .into_iter()
+                        .map(|edge| NetError::UnwiredOutEdge {
+                            transition: name.clone(),
+                            edge,
+                        }),
+                );
                 (
                     name,
                     TransitionRuntime {
-                        t: t,
+                        t: Some(t),
                         description: d,
-                        in_edge_to_place: in_edge_to_place,
-                        out_edge_to_place: out_edge_to_place,
+                        in_edge_to_place,
+                        out_edge_to_place,
+                        in_weights,
+                        out_weights,
+                        inhibitors,
+                        priority,
+                        delay,
+                        fault_policy,
                     },
                 )
             })
             .collect::<HashMap<_, _>>();
-        Self {
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+        let bounds = n
+            .capacities
+            .iter()
+            .filter_map(|(p_name, (_, backpressure))| {
+                slots.remove(p_name).map(|slots| {
+                    let owned = !output_places.contains_key(p_name);
+                    (p_name.clone(), Bound::make(*backpressure, slots, owned))
+                })
+            })
+            .collect();
+        Ok(Self {
             state: State::make(
                 n.places,
+                n.timers,
+                bounds,
+                n.disciplines,
                 input_places,
                 output_places,
                 state_delta_notification,
+                wake,
                 exit_rx,
             ),
-            transitions: transitions,
-            plot_sink: plot_sink,
+            transitions,
+            plot_sink,
+            policy: Box::new(FirstMatch::make()),
+            async_calls: HashMap::new(),
+            next_async_call: 0,
+            recorder: None,
+            replay: None,
+            tracer: None,
+            observers: (0, Observers::default()),
+            start: Instant::now(),
+            blocked_since: None,
+        })
+    }
+    pub fn set_scheduling_policy(&mut self, policy: Box<dyn SchedulingPolicy>) {
+        self.policy = policy;
+    }
+    pub fn record(&mut self, index: usize, tx: Sender<Firing>) {
+        self.recorder = Some((index, tx));
+    }
+    pub fn replay(&mut self, index: usize, replay: Arc<Replay>) {
+        self.replay = Some((index, replay));
+    }
+    pub fn observe(&mut self, index: usize, observers: Observers) {
+        self.state.observe(index, observers.clone());
+        self.observers = (index, observers);
+    }
+    pub fn trace(&mut self, tracer: Tracer) {
+        self.state.trace(tracer.clone());
+        self.tracer = Some(tracer);
+    }
+    pub fn add_input(&mut self, place: String, rx: Receiver<StateBlockable>) {
+        self.state.add_input(place, rx);
+    }
+    pub fn subscribe(&mut self, p_ty: (String, TypeId), tx: Sender<StateBlockable>) {
+        self.state.subscribe(p_ty, tx);
+    }
+    pub fn disable_blocking(&mut self) {
+        self.state.disable_blocking();
+    }
+    pub fn println(&self, s: &str) {
+        if let Ok(mut plot_sink) = self.plot_sink.lock() {
+            plot_sink.println(s);
+        }
+    }
+    pub fn nonblocking_states(&self) -> HashSet<NonblockingState> {
+        let mut nonblocking_states = HashSet::new();
+        for (t_name, t_run) in &self.transitions {
+            for (f_name, case) in &t_run.description.cases {
+                for (i, cond) in case.inputs.iter().enumerate() {
+                    nonblocking_states.insert(NonblockingState {
+                        weights: cond
+                            .iter()
+                            .filter(|p_ty| !self.state.timed(&p_ty.0))
+                            .map(|p_ty| (p_ty.clone(), t_run.in_weight(f_name, &p_ty.0)))
+                            .collect(),
+                        inhibitors: t_run.inhibitors.iter().cloned().collect(),
+                        bounds: t_run
+                            .out_edge_to_place
+                            .right_values()
+                            .filter_map(|p| {
+                                let weight = *t_run.out_weights.get(p).unwrap_or(&1);
+                                self.state
+                                    .capacity(p)
+                                    .map(|capacity| (p.clone(), (weight, capacity)))
+                            })
+                            .collect(),
+                        guard: if case.guarded[i] {
+                            Some((t_name.clone(), f_name.clone(), i))
+                        } else {
+                            None
+                        },
+                    });
+                }
+            }
+        }
+        nonblocking_states
+    }
+    fn fault(&mut self, t_name: &str, f_name: &str, payload: Box<dyn Any + Send>) -> bool {
+        let fault = TransitionFault::make(t_name, f_name, payload);
+        self.plot_sink
+            .lock()
+            .unwrap()
+            .println(&format!("{}", fault));
+        let t_run = self.transitions.get_mut(t_name).unwrap();
+        match &mut t_run.fault_policy {
+            FaultPolicy::Restart(maker) => t_run.t = Some(maker()),
+            FaultPolicy::DropTokens => {}
+            FaultPolicy::RouteTo(place) => {
+                let p_ty = (place.clone(), TypeId::of::<TransitionFault>());
+                self.state.push(&p_ty, Token::new(fault));
+            }
+            FaultPolicy::Shutdown => {
+                self.state.fault(fault);
+                return true;
+            }
+        }
+        false
+    }
+    fn poll_async(&mut self, id: usize) -> bool {
+        let mut call = match self.async_calls.remove(&id) {
+            Some(call) => call,
+            None => return false,
+        };
+        let waker = Waker::from(Arc::new(ClusterWaker {
+            id,
+            tx: self.state.waker(),
+        }));
+        let mut cx = Context::from_waker(&waker);
+        let begin = Instant::now();
+        let polled = catch_unwind(AssertUnwindSafe(|| call.future.as_mut().poll(&mut cx)));
+        self.traced("async", &call.t_name, begin, json!({ "case": call.f_name }));
+        let exit = match polled {
+            Ok(Poll::Pending) => {
+                self.async_calls.insert(id, call);
+                return false;
+            }
+            Ok(Poll::Ready((t, out_map, _))) => {
+                self.fire_end(&call.t_name, &call.f_name, call.launched);
+                let t_run = self.transitions.get_mut(&call.t_name).unwrap();
+                t_run.t = Some(t);
+                match t_run.push_outputs(&call.f_name, out_map, &mut self.state) {
+                    Ok(()) => false,
+                    Err(message) => self.fault(&call.t_name, &call.f_name, Box::new(message)),
+                }
+            }
+            Err(payload) => {
+                self.fire_end(&call.t_name, &call.f_name, call.launched);
+                if self.fault(&call.t_name, &call.f_name, payload) {
+                    true
+                } else if self.transitions[&call.t_name].t.is_none() {
+                    self.state.fault(TransitionFault::make(
+                        &call.t_name,
+                        &call.f_name,
+                        Box::new("instance lost in async case without a restart policy"),
+                    ));
+                    true
+                } else {
+                    false
+                }
+            }
+        };
+        self.state.end_async();
+        self.state.state_delta_complete();
+        exit
+    }
+    fn fire_start(&self, t_name: &str, f_name: &str, i: usize) {
+        let (index, observers) = &self.observers;
+        observers.notify(|o| o.on_fire_start(*index, t_name, f_name, i));
+    }
+    fn fire_end(&self, t_name: &str, f_name: &str, begin: Instant) {
+        let (index, observers) = &self.observers;
+        let elapsed = Instant::now() - begin;
+        observers.notify(|o| o.on_fire_end(*index, t_name, f_name, elapsed));
+    }
+    fn traced(&self, cat: &str, name: &str, begin: Instant, args: Value) {
+        if let Some(tracer) = &self.tracer {
+            tracer.slice(cat, name, begin, args);
+        }
+    }
+    fn fired(&mut self, turn: &Turn, t_name: &str, f_name: &str, i: usize, product: Option<usize>) {
+        let firing = |work_cluster| Firing {
+            time: (Instant::now() - self.start).as_secs_f64(),
+            work_cluster,
+            transition: t_name.into(),
+            case: f_name.into(),
+            condition: i,
+            product,
+        };
+        if let Some((index, tx)) = &self.recorder {
+            let _ = tx.send(firing(*index));
+        }
+        if let Turn::Fire(step, f) = turn {
+            if f.product != product {
+                self.plot_sink.lock().unwrap().println(&format!(
+                    "replay diverged at firing {}: {}: {} produced {:?} instead of {:?}",
+                    step, t_name, f_name, product, f.product
+                ));
+            }
+        }
+    }
+    fn advance(&self, turn: &Turn) {
+        if let (Some((_, replay)), Turn::Fire(_, _)) = (&self.replay, turn) {
+            replay.advance();
         }
     }
-    pub fn nonblocking_states(&self) -> HashSet<BTreeSet<(String, TypeId)>> {
-        self.transitions
+    fn report_rejected_guards(&mut self) {
+        let mut rejected = false;
+        for (t_name, t_run) in self
+            .transitions
             .iter()
-            .map(|(_, t_run)| &t_run.description.cases)
-            .flatten()
-            .map(|(_, case)| {
-                case.inputs
-                    .iter()
-                    .map(|cond| cond.iter().cloned().collect())
-                    .collect::<HashSet<_>>()
-            })
-            .flatten()
-            .collect()
-    }
-    pub fn run(
-        mut self,
-        plot_options: PlotOptions,
-    ) -> HashMap<String, HashMap<TypeId, VecDeque<Token>>> {
-        let start = Instant::now();
-        if plot_options.reactor_timing {
-            self.plot_sink
-                .plot_series_2d("reactor timing", "blocking", 0.0, 0.0);
-            self.plot_sink
-                .plot_series_2d("reactor timing", "nonblocking", 0.0, 0.0);
-        }
-        if plot_options.transition_timing {
-            for (t_name, _t_run) in &self.transitions {
-                self.plot_sink
-                    .plot_series_2d("transition timing", t_name, 0.0, 0.0);
+            .filter(|(_, t_run)| t_run.t.is_some())
+        {
+            for (f_name, case) in &t_run.description.cases {
+                for (i, condition) in case.inputs.iter().enumerate() {
+                    if case.guarded[i] && t_run.enabled(f_name, condition, &self.state) {
+                        let snapshot = condition
+                            .iter()
+                            .map(|p_ty| (p_ty.clone(), self.state.count(p_ty)))
+                            .collect();
+                        self.state
+                            .reject((t_name.clone(), f_name.clone(), i), snapshot);
+                        rejected = true;
+                    }
+                }
             }
         }
+        if rejected {
+            self.state.state_delta_complete();
+        }
+    }
+    pub fn begin(&mut self) {
+        self.start = Instant::now();
+        let (index, observers) = &self.observers;
+        let transitions = self
+            .transitions
+            .keys()
+            .map(|t_name| t_name.as_str())
+            .sorted()
+            .collect::<Vec<_>>();
+        observers.notify(|o| o.on_start(*index, &transitions));
+    }
+    pub fn fire(&mut self) -> bool {
         let mut exit = false;
-        while !exit {
-            let mut blocked = false;
-            while !blocked {
-                let mut last_nonblocking_time = (Instant::now() - start).as_secs_f64();
+        let mut blocked = false;
+        if let Some(since) = self.blocked_since.take() {
+            self.traced("reactor", "blocked", since, json!({}));
+            let (index, observers) = &self.observers;
+            let blocked = Instant::now() - since;
+            observers.notify(|o| o.on_unblock(*index, blocked));
+        }
+        while !blocked {
+            exit = self.state.refresh();
+            for id in self.state.take_woken() {
+                exit |= self.poll_async(id);
+            }
+            if self.async_calls.is_empty() {
+                self.state.settle();
+            }
+            if exit || self.state.paused() {
                 blocked = true;
-                for (t_name, t_run) in self.transitions.iter_mut() {
-                    for (f_name, case) in &t_run.description.cases {
-                        for (i, condition) in case.inputs.iter().enumerate() {
-                            let state_plotting = if plot_options.local_state {
-                                Some((&mut self.plot_sink, (Instant::now() - start).as_secs_f64()))
-                            } else {
-                                None
-                            };
-                            let e_bin = self.state.binary(state_plotting);
-                            exit = e_bin.0;
-                            if (condition - e_bin.1).len() == 0 {
-                                let mut in_map = HashMap::new();
-                                for p_ty in condition {
-                                    in_map.insert(
-                                        (
-                                            t_run
-                                                .in_edge_to_place
-                                                .get_by_right(&p_ty.0)
-                                                .unwrap()
-                                                .clone(),
-                                            p_ty.1.clone(),
-                                        ),
-                                        self.state.pop(p_ty),
-                                    );
-                                }
-                                let mut out_map = HashMap::new();
-                                let elapsed = (Instant::now() - start).as_secs_f64();
-                                if plot_options.reactor_timing {
-                                    self.plot_sink.plot_series_2d(
-                                        "reactor timing",
-                                        "nonblocking",
-                                        elapsed,
-                                        elapsed - last_nonblocking_time,
-                                    );
-                                }
-                                t_run.t.call(&f_name, i, &mut in_map, &mut out_map);
-                                let elapsed2 = (Instant::now() - start).as_secs_f64();
-                                last_nonblocking_time = elapsed2;
-                                if plot_options.transition_timing {
-                                    self.plot_sink.plot_series_2d(
-                                        "transition timing",
-                                        &t_name,
-                                        elapsed2,
-                                        elapsed2 - elapsed,
-                                    );
-                                }
-                                for ((e_name, ty), t) in out_map.into_iter() {
-                                    let place = t_run
-                                        .out_edge_to_place
-                                        .get_by_left(&e_name)
-                                        .unwrap()
-                                        .clone();
-                                    self.state.push(&(place, ty), t);
-                                }
-                                self.state.state_delta_complete();
-                                blocked = false;
-                                break;
+                continue;
+            }
+            let turn = match &self.replay {
+                Some((index, replay)) => replay.turn(*index),
+                None => Turn::Free,
+            };
+            if let Turn::Wait = turn {
+                blocked = true;
+                continue;
+            }
+            let mut candidates = vec![];
+            for (t_name, t_run) in self.transitions.iter().sorted_by_key(|x| x.0) {
+                let t = match &t_run.t {
+                    Some(t) => t,
+                    None => continue,
+                };
+                for (f_name, case) in t_run.description.cases.iter().sorted_by_key(|x| x.0) {
+                    for (i, condition) in case.inputs.iter().enumerate() {
+                        if t_run.enabled(f_name, condition, &self.state) {
+                            let mut view = GuardView::make(&self.state, &t_run.in_edge_to_place);
+                            if t.guard(f_name, i, &mut view) {
+                                candidates.push((
+                                    t_name.clone(),
+                                    f_name.clone(),
+                                    i,
+                                    view.take_selected(),
+                                    (t_run.priority, case.priority),
+                                ));
                             }
                         }
                     }
                 }
             }
-            if !exit {
-                let elapsed = (Instant::now() - start).as_secs_f64();
-                exit = self.state.block_rx();
-                if plot_options.reactor_timing {
-                    let elapsed2 = (Instant::now() - start).as_secs_f64();
-                    let blocking_time = elapsed2 - elapsed;
-                    self.plot_sink.plot_series_2d(
-                        "reactor timing",
-                        "blocking",
-                        elapsed2,
-                        blocking_time,
+            if candidates.is_empty() {
+                blocked = true;
+                continue;
+            }
+            let choice = match &turn {
+                Turn::Fire(_, f) => match candidates.iter().position(|(t_name, f_name, i, _, _)| {
+                    t_name == &f.transition && f_name == &f.case && *i == f.condition
+                }) {
+                    Some(choice) => choice,
+                    None => {
+                        blocked = true;
+                        continue;
+                    }
+                },
+                _ => {
+                    let top = candidates.iter().map(|c| c.4).max().unwrap();
+                    candidates.retain(|c| c.4 == top);
+                    let choice = self.policy.select(
+                        &candidates
+                            .iter()
+                            .map(|(t_name, f_name, i, _, _)| Candidate {
+                                transition: t_name,
+                                case: f_name,
+                                condition: *i,
+                            })
+                            .collect::<Vec<_>>(),
                     );
+                    choice.min(candidates.len() - 1)
                 }
+            };
+            let (t_name, f_name, i, selected, _) = candidates.swap_remove(choice);
+            self.fire_start(&t_name, &f_name, i);
+            let t_run = self.transitions.get_mut(&t_name).unwrap();
+            let case = &t_run.description.cases[&f_name];
+            let condition = &case.inputs[i];
+            let mut in_map = HashMap::new();
+            for p_ty in condition {
+                let e_name = t_run
+                    .in_edge_to_place
+                    .get_by_right(&p_ty.0)
+                    .unwrap()
+                    .clone();
+                let idx = selected.get(p_ty).cloned().unwrap_or(0);
+                let arity = t_run
+                    .description
+                    .in_arity
+                    .get(&(f_name.clone(), e_name.clone()));
+                let token = match arity {
+                    Some(EdgeArity::Single) | None => self.state.pop(p_ty, idx),
+                    Some(_) => {
+                        let weight = t_run.in_weights[&(f_name.clone(), e_name.clone())];
+                        let start = idx.min(self.state.count(p_ty) - weight);
+                        Token::new(
+                            (0..weight)
+                                .map(|_| self.state.pop(p_ty, start))
+                                .collect::<Vec<Token>>(),
+                        )
+                    }
+                };
+                in_map.insert((e_name, p_ty.1), token);
             }
+            if case.asynchronous {
+                let mut t = t_run.t.take().unwrap();
+                let id = self.next_async_call;
+                self.next_async_call += 1;
+                let case_name = f_name.clone();
+                self.fired(&turn, &t_name, &f_name, i, None);
+                self.async_calls.insert(
+                    id,
+                    AsyncCall {
+                        t_name,
+                        f_name,
+                        launched: Instant::now(),
+                        future: Box::pin(async move {
+                            let (out_map, product) = t.call_async(&case_name, i, in_map).await;
+                            (t, out_map, product)
+                        }),
+                    },
+                );
+                self.state.begin_async();
+                self.state.state_delta_complete();
+                self.advance(&turn);
+                exit = self.poll_async(id);
+                blocked = exit;
+                continue;
+            }
+            let mut out_map = HashMap::new();
+            let begin = Instant::now();
+            let t = t_run.t.as_mut().unwrap();
+            let called = catch_unwind(AssertUnwindSafe(|| {
+                t.call(&f_name, i, &mut in_map, &mut out_map)
+            }));
+            self.fire_end(&t_name, &f_name, begin);
+            let pushed = match called {
+                Ok(product) => {
+                    self.fired(&turn, &t_name, &f_name, i, Some(product));
+                    self.transitions[&t_name]
+                        .push_outputs(&f_name, out_map, &mut self.state)
+                        .map(|_| product)
+                        .map_err(|message| Box::new(message) as Box<dyn Any + Send>)
+                }
+                Err(payload) => {
+                    self.fired(&turn, &t_name, &f_name, i, None);
+                    Err(payload)
+                }
+            };
+            let product = match pushed {
+                Ok(product) => product,
+                Err(payload) => {
+                    exit = self.fault(&t_name, &f_name, payload);
+                    self.traced(
+                        "transition",
+                        &t_name,
+                        begin,
+                        json!({ "case": f_name, "condition": i, "fault": true }),
+                    );
+                    blocked = exit;
+                    self.state.state_delta_complete();
+                    self.advance(&turn);
+                    continue;
+                }
+            };
+            self.traced(
+                "transition",
+                &t_name,
+                begin,
+                json!({ "case": f_name, "condition": i, "product": product }),
+            );
+            self.state.state_delta_complete();
+            self.advance(&turn);
+        }
+        if !exit && !self.state.paused() {
+            self.report_rejected_guards();
+        }
+        if !exit {
+            self.blocked_since = Some(Instant::now());
+            let (index, observers) = &self.observers;
+            observers.notify(|o| o.on_block(*index));
         }
+        exit
+    }
+    fn block(&mut self) -> bool {
+        self.state.block_rx()
+    }
+    pub fn wait_set(&self) -> (Vec<Receiver<StateBlockable>>, Option<Instant>) {
+        self.state.wait_set()
+    }
+    pub fn take_places(self) -> HashMap<String, HashMap<TypeId, VecDeque<Token>>> {
+        if let Some(since) = self.blocked_since {
+            self.traced("reactor", "blocked", since, json!({}));
+        }
+        let (index, observers) = &self.observers;
+        observers.notify(|o| o.on_exit(*index));
         self.state.take_places()
     }
+    pub fn run(mut self) -> HashMap<String, HashMap<TypeId, VecDeque<Token>>> {
+        self.begin();
+        while !self.fire() {
+            if self.block() {
+                break;
+            }
+        }
+        self.take_places()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::countdown::{Countdown, N};
+    use crate::testing::{batch::*, run, tokens, try_work_cluster};
+    use crate::transition::Describe;
+    use crate::{FaultPolicy, GuardView, Net, NetError, Token, TransitionFault};
+
+    #[derive(crate::TransitionOutputTokensMacro)]
+    struct Stopped {
+        stopped: u32,
+    }
+    #[derive(crate::TransitionOutputTokensMacro)]
+    struct Worked {
+        worked: u32,
+    }
+    #[derive(crate::Transition)]
+    #[ntpnet_transition(stop: StopIn(N) -> StopOut(Stopped))]
+    #[ntpnet_transition(work: WorkIn(N) -> WorkOut(Worked))]
+    #[ntpnet_priority(work: -1)]
+    struct Triage {}
+    impl Triage {
+        fn stop(&mut self, i: StopIn) -> StopOut {
+            let StopIn::N(N { n }) = i;
+            StopOut::Stopped(Stopped { stopped: n })
+        }
+        fn work(&mut self, i: WorkIn) -> WorkOut {
+            let WorkIn::N(N { n }) = i;
+            WorkOut::Worked(Worked { worked: n })
+        }
+    }
+
+    #[derive(crate::Transition)]
+    #[ntpnet_transition(pick: PickIn(Pair) -> PickOut(Total))]
+    #[ntpnet_guard(Pair: even)]
+    struct Pick {}
+    impl Pick {
+        fn even(&self, view: &mut GuardView) -> bool {
+            view.find("n", |n: &u32| n.is_multiple_of(2))
+        }
+        fn pick(&mut self, i: PickIn) -> PickOut {
+            let PickIn::Pair(Pair { n }) = i;
+            PickOut::Total(Total {
+                total: n[0] * 10 + n[1],
+            })
+        }
+    }
+
+    #[test]
+    fn weighted_edges_consume_and_produce_several_tokens() {
+        let places = run(Net::make()
+            .set_start_tokens("N", (1..=4u32).map(Token::new).collect())
+            .set_start_tokens("M", vec![Token::new(2u32)])
+            .add_typed_transition("sum", || Sum {})
+            .add_typed_transition("spread", || Spread {})
+            .place_to_transition_weighted("N", "n", "sum", 2)
+            .transition_to_place("sum", "total", "T")
+            .place_to_transition("M", "n", "spread")
+            .transition_to_place_weighted("spread", "copies", "C", 2));
+        assert_eq!(tokens::<u32>(&places, "T"), vec![3, 7]);
+        assert_eq!(tokens::<u32>(&places, "C"), vec![2, 2]);
+    }
+
+    #[test]
+    fn in_weights_follow_the_case() {
+        let places = run(Net::make()
+            .set_start_tokens("N", vec![Token::new(5u32)])
+            .add_typed_transition("b", || Batch {})
+            .place_to_transition("N", "n", "b")
+            .transition_to_place("b", "total", "T"));
+        assert_eq!(tokens::<u32>(&places, "T"), vec![5]);
+    }
+
+    #[test]
+    fn weighted_edges_consume_from_the_guard_selection() {
+        let net = |n: Vec<u32>| {
+            Net::make()
+                .set_start_tokens("N", n.into_iter().map(Token::new).collect())
+                .add_typed_transition("p", || Pick {})
+                .place_to_transition("N", "n", "p")
+                .transition_to_place("p", "total", "T")
+        };
+        let places = run(net(vec![1, 2, 3]));
+        assert_eq!(tokens::<u32>(&places, "T"), vec![23]);
+        assert_eq!(tokens::<u32>(&places, "N"), vec![1]);
+        let places = run(net(vec![1, 2]));
+        assert_eq!(tokens::<u32>(&places, "T"), vec![12]);
+    }
+
+    #[test]
+    fn weighted_output_length_mismatch_is_a_fault() {
+        let places = run(Net::make()
+            .set_start_tokens("M", vec![Token::new(1u32)])
+            .add_typed_transition("spread", || Spread {})
+            .place_to_transition("M", "n", "spread")
+            .transition_to_place_weighted("spread", "copies", "C", 2)
+            .set_fault_policy("spread", FaultPolicy::RouteTo("F".into())));
+        assert!(tokens::<u32>(&places, "C").is_empty());
+        assert_eq!(
+            tokens::<TransitionFault>(&places, "F"),
+            vec![TransitionFault {
+                transition: "spread".into(),
+                case: "spread".into(),
+                message: "edge copies produced 1 tokens for weight 2".into(),
+            }]
+        );
+    }
+
+    #[test]
+    fn highest_priority_case_fires() {
+        assert_eq!(Triage::describe().cases["work"].priority, -1);
+        let net = Net::make()
+            .set_start_tokens("N", vec![Token::new(1u32), Token::new(2u32)])
+            .add_typed_transition("t", || Triage {})
+            .place_to_transition("N", "n", "t")
+            .transition_to_place("t", "stopped", "S")
+            .transition_to_place("t", "worked", "W");
+        let (nodes, _) = net.as_dot(false);
+        assert!(nodes.contains("work: priority -1"));
+        let places = run(net);
+        assert_eq!(tokens::<u32>(&places, "S"), vec![1, 2]);
+        assert!(tokens::<u32>(&places, "W").is_empty());
+    }
+
+    #[test]
+    fn highest_priority_transition_fires() {
+        let net = ["a", "b"].iter().fold(
+            Net::make().set_start_tokens("N", vec![Token::new(0u32), Token::new(0u32)]),
+            |net, t| {
+                net.add_typed_transition(t, || Countdown {})
+                    .place_to_transition("N", "n", t)
+                    .transition_to_place(t, "n", "N")
+                    .transition_to_place(t, "done", &t.to_uppercase())
+            },
+        );
+        let places = run(net.set_priority("b", 1));
+        assert!(tokens::<u32>(&places, "A").is_empty());
+        assert_eq!(tokens::<u32>(&places, "B"), vec![0, 0]);
+    }
+
+    #[test]
+    fn try_make_reports_unwired_edges() {
+        let net = Net::make()
+            .set_start_tokens("N", vec![Token::new(3u32)])
+            .add_transition("c", Box::new(|| Box::new(Countdown {})))
+            .place_to_transition("N", "n", "c")
+            .transition_to_place("c", "n", "N");
+        assert_eq!(
+            try_work_cluster(net).err(),
+            Some(vec![NetError::UnwiredOutEdge {
+                transition: "c".into(),
+                edge: "done".into(),
+            }])
+        );
+    }
 }